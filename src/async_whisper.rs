@@ -0,0 +1,175 @@
+//! Async wrapper around [WhisperContext], for use inside a tokio runtime.
+
+use crate::error::WhisperError;
+use crate::segment::Segment;
+use crate::transcript::Transcript;
+use crate::whisper_ctx::WhisperContext;
+use crate::whisper_params::FullParams;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+
+/// Owns a [WhisperContext] and runs transcriptions on tokio's blocking thread pool,
+/// so they don't stall the async runtime.
+///
+/// Cloning is cheap and shares the context. Transcriptions on the same context run one at a time.
+#[derive(Debug, Clone)]
+pub struct AsyncWhisper {
+    ctx: Arc<Mutex<WhisperContext>>,
+}
+
+/// Sets the abort flag when the transcription future is dropped before it completes.
+#[derive(Debug)]
+struct AbortOnDrop(Option<Arc<AtomicBool>>);
+
+impl AbortOnDrop {
+    fn disarm(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        if let Some(abort) = &self.0 {
+            abort.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+impl AsyncWhisper {
+    /// Wrap a context.
+    pub fn new(ctx: WhisperContext) -> Self {
+        Self {
+            ctx: Arc::new(Mutex::new(ctx)),
+        }
+    }
+
+    /// Run [WhisperContext::full] on a blocking thread and collect the segments.
+    ///
+    /// Dropping the returned future cancels the transcription: it stops before the encoder runs
+    /// on the next 30 second window, which frees the context for the next call.
+    /// Any abort flag set on `params` is replaced.
+    ///
+    /// # Arguments
+    /// * params: [FullParams] struct. Borrowed prompt tokens must be `'static`, since the call outlives this function.
+    /// * audio: 16 kHz mono PCM audio data.
+    ///
+    /// # Returns
+    /// Ok([Transcript]) on success, Err(WhisperError) on failure.
+    /// Err(WhisperError::Aborted) if the runtime shut down before the transcription ran.
+    ///
+    /// # Panics
+    /// Panics if called outside of a tokio runtime, or if the transcription panicked.
+    pub async fn full(
+        &self,
+        mut params: FullParams<'static, 'static>,
+        audio: Vec<f32>,
+    ) -> Result<Transcript, WhisperError> {
+        let abort = Arc::new(AtomicBool::new(false));
+        params.set_abort_flag(Arc::clone(&abort));
+        let guard = AbortOnDrop(Some(abort));
+        let ctx = Arc::clone(&self.ctx);
+        let result = tokio::task::spawn_blocking(move || {
+            // a panic in a previous transcription doesn't leave the context in an unusable state
+            let mut ctx = ctx.lock().unwrap_or_else(|e| e.into_inner());
+            let timings = ctx.full(params, &audio)?;
+            Ok(Transcript {
+                segments: ctx.full_get_segments()?,
+                timings,
+            })
+        })
+        .await;
+        // finished, nothing to cancel anymore
+        guard.disarm();
+        match result {
+            Ok(result) => result,
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Err(_) => Err(WhisperError::Aborted),
+        }
+    }
+
+    /// Run [WhisperContext::full] on a blocking thread, and stream the segments while they are decoded.
+    ///
+    /// The transcription starts right away, even if the stream isn't polled.
+    /// Dropping the stream cancels it like dropping the future of [full](AsyncWhisper::full) does.
+    /// Any segment callback or abort flag set on `params` is replaced.
+    ///
+    /// # Arguments
+    /// * params: [FullParams] struct. Borrowed prompt tokens must be `'static`, since the call outlives this function.
+    /// * audio: 16 kHz mono PCM audio data.
+    ///
+    /// # Returns
+    /// A [SegmentStream] yielding every segment, then an error if the transcription failed.
+    ///
+    /// # Panics
+    /// Panics if called outside of a tokio runtime. The stream panics if the transcription panicked.
+    pub fn full_stream(
+        &self,
+        mut params: FullParams<'static, 'static>,
+        audio: Vec<f32>,
+    ) -> SegmentStream {
+        let abort = Arc::new(AtomicBool::new(false));
+        params.set_abort_flag(Arc::clone(&abort));
+        let (sender, segments) = mpsc::unbounded_channel();
+        let ctx = Arc::clone(&self.ctx);
+        let task = tokio::task::spawn_blocking(move || {
+            let mut ctx = ctx.lock().unwrap_or_else(|e| e.into_inner());
+            let on_segment = sender.clone();
+            params.set_segment_callback(move |segment| {
+                let _ = on_segment.send(Ok(segment));
+            });
+            if let Err(e) = ctx.full(params, &audio) {
+                let _ = sender.send(Err(e));
+            }
+        });
+        SegmentStream {
+            segments,
+            task: Some(task),
+            _abort: AbortOnDrop(Some(abort)),
+        }
+    }
+
+    /// Get the context back, if this is the last handle to it and no transcription is running.
+    pub fn into_inner(self) -> Result<WhisperContext, Self> {
+        match Arc::try_unwrap(self.ctx) {
+            Ok(ctx) => Ok(ctx.into_inner().unwrap_or_else(|e| e.into_inner())),
+            Err(ctx) => Err(Self { ctx }),
+        }
+    }
+}
+
+/// Segments of a transcription started by [AsyncWhisper::full_stream].
+///
+/// Implements [futures_core::Stream], yielding `Ok` for every segment in order,
+/// then a single `Err` if the transcription failed.
+#[derive(Debug)]
+pub struct SegmentStream {
+    segments: mpsc::UnboundedReceiver<Result<Segment, WhisperError>>,
+    task: Option<tokio::task::JoinHandle<()>>,
+    _abort: AbortOnDrop,
+}
+
+impl futures_core::Stream for SegmentStream {
+    type Item = Result<Segment, WhisperError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.segments.poll_recv(cx) {
+            Poll::Ready(None) => {
+                // the sender is gone, the task ended: surface a panic instead of ending quietly
+                let Some(task) = self.task.as_mut() else {
+                    return Poll::Ready(None);
+                };
+                let result = std::task::ready!(Pin::new(task).poll(cx));
+                self.task = None;
+                match result {
+                    Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+                    _ => Poll::Ready(None),
+                }
+            }
+            poll => poll,
+        }
+    }
+}
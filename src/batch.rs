@@ -0,0 +1,423 @@
+//! Transcription of many files across a pool of contexts.
+
+use crate::error::WhisperError;
+use crate::output::OutputFormat;
+use crate::parallel::ContextPool;
+use crate::timings::Timings;
+use crate::whisper_ctx::WhisperContext;
+use crate::whisper_params::FullParams;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Transcribes a list of audio files, one output file per input and format.
+///
+/// Every context of the [ContextPool] runs one file at a time, so the pool size bounds the number
+/// of files in progress and the memory used.
+/// Failures are recorded in the [BatchReport] and don't stop the other files.
+#[derive(Debug, Clone)]
+pub struct BatchRunner {
+    /// Formats to write for every input.
+    ///
+    /// Defaults to [OutputFormat::Txt].
+    pub formats: Vec<OutputFormat>,
+    /// Directory to write outputs to. Outputs are named after the input file, with the extension of the format.
+    /// Inputs with the same name before the extension would write the same outputs, so they fail instead.
+    ///
+    /// Defaults to None, writing next to each input.
+    pub output_dir: Option<PathBuf>,
+    /// Transcribe inputs even if all their outputs are newer than the input.
+    ///
+    /// Defaults to false.
+    pub force: bool,
+}
+
+impl Default for BatchRunner {
+    fn default() -> Self {
+        Self {
+            formats: vec![OutputFormat::Txt],
+            output_dir: None,
+            force: false,
+        }
+    }
+}
+
+/// Why a single input of a batch failed.
+#[derive(Debug)]
+pub enum BatchError {
+    /// The audio could not be loaded, with the message of the loader.
+    Audio(String),
+    /// Transcribing failed.
+    Whisper(WhisperError),
+    /// Writing an output failed.
+    Output(PathBuf, io::Error),
+    /// Another input, given here, would write to the same output path.
+    OutputCollision(PathBuf),
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Audio(e) => write!(f, "failed to load audio: {}", e),
+            Self::Whisper(e) => write!(f, "{}", e),
+            Self::Output(path, e) => write!(f, "failed to write {}: {}", path.display(), e),
+            Self::OutputCollision(other) => {
+                write!(f, "{} would write the same output", other.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for BatchError {}
+
+/// What happened to a single input of a batch.
+#[derive(Debug)]
+pub enum BatchOutcome {
+    /// The input was transcribed and its outputs written.
+    Transcribed(Timings),
+    /// All outputs were already newer than the input.
+    UpToDate,
+    /// The input failed, no outputs were written for it.
+    Failed(BatchError),
+}
+
+/// A single input of a batch, and what happened to it.
+#[derive(Debug)]
+pub struct BatchItem {
+    /// Path of the input.
+    pub input: PathBuf,
+    /// Paths of the outputs, one per format.
+    pub outputs: Vec<PathBuf>,
+    /// The result.
+    pub outcome: BatchOutcome,
+}
+
+/// Summary of a [BatchRunner::run] call. Displays as a human readable report.
+#[derive(Debug)]
+pub struct BatchReport {
+    /// Every input, in the order they were passed in.
+    pub items: Vec<BatchItem>,
+    /// Wall-clock time of the whole batch.
+    pub elapsed: Duration,
+}
+
+impl BatchReport {
+    /// Timings of the inputs that were transcribed.
+    pub fn transcribed(&self) -> impl Iterator<Item = (&BatchItem, &Timings)> {
+        self.items.iter().filter_map(|item| match &item.outcome {
+            BatchOutcome::Transcribed(timings) => Some((item, timings)),
+            _ => None,
+        })
+    }
+
+    /// Inputs that were skipped because their outputs were up to date.
+    pub fn up_to_date(&self) -> impl Iterator<Item = &BatchItem> {
+        self.items
+            .iter()
+            .filter(|item| matches!(item.outcome, BatchOutcome::UpToDate))
+    }
+
+    /// Inputs that failed, with the reason.
+    pub fn failed(&self) -> impl Iterator<Item = (&BatchItem, &BatchError)> {
+        self.items.iter().filter_map(|item| match &item.outcome {
+            BatchOutcome::Failed(e) => Some((item, e)),
+            _ => None,
+        })
+    }
+
+    /// Total duration of the transcribed audio.
+    pub fn audio(&self) -> Duration {
+        self.transcribed().map(|(_, t)| t.audio).sum()
+    }
+
+    /// Time spent transcribing, added up over all contexts.
+    pub fn processing(&self) -> Duration {
+        self.transcribed().map(|(_, t)| t.total).sum()
+    }
+
+    /// Processing time divided by the transcribed audio duration, as in [Timings::real_time_factor].
+    ///
+    /// # Returns
+    /// 0 if no audio was transcribed.
+    pub fn real_time_factor(&self) -> f64 {
+        let audio = self.audio();
+        if audio.is_zero() {
+            0.0
+        } else {
+            self.processing().as_secs_f64() / audio.as_secs_f64()
+        }
+    }
+}
+
+impl fmt::Display for BatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} inputs: {} transcribed, {} up to date, {} failed",
+            self.items.len(),
+            self.transcribed().count(),
+            self.up_to_date().count(),
+            self.failed().count()
+        )?;
+        writeln!(
+            f,
+            "audio {:.1} s, processing {:.1} s, wall clock {:.1} s, real-time factor {:.3}",
+            self.audio().as_secs_f64(),
+            self.processing().as_secs_f64(),
+            self.elapsed.as_secs_f64(),
+            self.real_time_factor()
+        )?;
+        for (item, e) in self.failed() {
+            writeln!(f, "failed: {}: {}", item.input.display(), e)?;
+        }
+        Ok(())
+    }
+}
+
+impl BatchRunner {
+    /// Transcribe every input. See [run_with](BatchRunner::run_with).
+    pub fn run<'a, 'b, L, P>(
+        &self,
+        pool: &ContextPool,
+        inputs: &[PathBuf],
+        load_audio: L,
+        make_params: P,
+    ) -> BatchReport
+    where
+        L: Fn(&Path) -> Result<Vec<f32>, String> + Sync,
+        P: Fn() -> FullParams<'a, 'b> + Sync,
+    {
+        self.run_with(pool, inputs, load_audio, make_params, |_| {})
+    }
+
+    /// Transcribe every input, calling `on_item` as each one finishes.
+    ///
+    /// Outputs are written to a temporary file next to the final path and renamed once complete,
+    /// so an interrupted batch never leaves an output that looks up to date.
+    /// Inputs sharing an output path with another input fail with [BatchError::OutputCollision]
+    /// before anything is transcribed.
+    ///
+    /// # Arguments
+    /// * pool: Contexts to run on, one input at a time each.
+    /// * inputs: Paths of the audio files.
+    /// * load_audio: Reads an input into 16 kHz mono PCM audio data.
+    /// * make_params: Creates the [FullParams] for every input.
+    /// * on_item: Called from the worker threads, in the order inputs finish.
+    ///
+    /// # Returns
+    /// A [BatchReport] listing every input.
+    pub fn run_with<'a, 'b, L, P, C>(
+        &self,
+        pool: &ContextPool,
+        inputs: &[PathBuf],
+        load_audio: L,
+        make_params: P,
+        on_item: C,
+    ) -> BatchReport
+    where
+        L: Fn(&Path) -> Result<Vec<f32>, String> + Sync,
+        P: Fn() -> FullParams<'a, 'b> + Sync,
+        C: Fn(&BatchItem) + Sync,
+    {
+        let start = Instant::now();
+        let collisions = self.collisions(inputs);
+        let next = AtomicUsize::new(0);
+        let items: Mutex<Vec<Option<BatchItem>>> =
+            Mutex::new(inputs.iter().map(|_| None).collect());
+        thread::scope(|scope| {
+            for ctx in pool.contexts.iter().take(inputs.len()) {
+                scope.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(input) = inputs.get(i) else {
+                        break;
+                    };
+                    let outputs = self.output_paths(input);
+                    let outcome = if let Some(other) = &collisions[i] {
+                        BatchOutcome::Failed(BatchError::OutputCollision(other.clone()))
+                    } else if !self.force && up_to_date(input, &outputs) {
+                        BatchOutcome::UpToDate
+                    } else {
+                        let mut ctx = ctx.lock().unwrap_or_else(|e| e.into_inner());
+                        match self.transcribe(&mut ctx, input, &outputs, &load_audio, &make_params)
+                        {
+                            Ok(timings) => BatchOutcome::Transcribed(timings),
+                            Err(e) => BatchOutcome::Failed(e),
+                        }
+                    };
+                    let item = BatchItem {
+                        input: input.clone(),
+                        outputs,
+                        outcome,
+                    };
+                    on_item(&item);
+                    items.lock().unwrap_or_else(|e| e.into_inner())[i] = Some(item);
+                });
+            }
+        });
+        BatchReport {
+            items: items
+                .into_inner()
+                .unwrap_or_else(|e| e.into_inner())
+                .into_iter()
+                .flatten()
+                .collect(),
+            elapsed: start.elapsed(),
+        }
+    }
+
+    /// Where the outputs of `input` are written, one path per format.
+    pub fn output_paths(&self, input: &Path) -> Vec<PathBuf> {
+        let dir = match &self.output_dir {
+            Some(dir) => dir.as_path(),
+            None => input.parent().unwrap_or(Path::new("")),
+        };
+        let stem = input.file_stem().unwrap_or(input.as_os_str());
+        self.formats
+            .iter()
+            .map(|format| {
+                let mut name = stem.to_owned();
+                name.push(".");
+                name.push(format.extension());
+                dir.join(name)
+            })
+            .collect()
+    }
+
+    /// For every input, another input writing to one of the same output paths, if any.
+    fn collisions(&self, inputs: &[PathBuf]) -> Vec<Option<PathBuf>> {
+        let mut collisions = vec![None; inputs.len()];
+        let mut writers: HashMap<PathBuf, usize> = HashMap::new();
+        for (i, input) in inputs.iter().enumerate() {
+            for output in self.output_paths(input) {
+                match writers.get(&output) {
+                    Some(&j) => {
+                        collisions[i] = Some(inputs[j].clone());
+                        collisions[j].get_or_insert_with(|| input.clone());
+                    }
+                    None => {
+                        writers.insert(output, i);
+                    }
+                }
+            }
+        }
+        collisions
+    }
+
+    fn transcribe<'a, 'b>(
+        &self,
+        ctx: &mut WhisperContext,
+        input: &Path,
+        outputs: &[PathBuf],
+        load_audio: impl Fn(&Path) -> Result<Vec<f32>, String>,
+        make_params: impl Fn() -> FullParams<'a, 'b>,
+    ) -> Result<Timings, BatchError> {
+        let audio = load_audio(input).map_err(BatchError::Audio)?;
+        let timings = ctx
+            .full(make_params(), &audio)
+            .map_err(BatchError::Whisper)?;
+        let segments = ctx.full_get_segments().map_err(BatchError::Whisper)?;
+        for (format, output) in self.formats.iter().zip(outputs) {
+            write_atomically(output, |writer| format.write(&segments, writer))
+                .map_err(|e| BatchError::Output(output.clone(), e))?;
+        }
+        Ok(timings)
+    }
+}
+
+/// Are all outputs at least as new as the input?
+fn up_to_date(input: &Path, outputs: &[PathBuf]) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    let Some(input) = modified(input) else {
+        return false;
+    };
+    outputs
+        .iter()
+        .all(|output| modified(output).is_some_and(|output| output >= input))
+}
+
+fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+    let result = File::create(&partial).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        write(&mut writer)?;
+        writer.flush()
+    });
+    match result.and_then(|()| fs::rename(&partial, path)) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let _ = fs::remove_file(&partial);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn outputs_and_freshness() {
+        let runner = BatchRunner {
+            formats: vec![OutputFormat::Srt, OutputFormat::Json],
+            output_dir: Some(PathBuf::from("out")),
+            force: false,
+        };
+        assert_eq!(
+            runner.output_paths(Path::new("calls/2024-01-02.call.wav")),
+            [
+                PathBuf::from("out/2024-01-02.call.srt"),
+                PathBuf::from("out/2024-01-02.call.json")
+            ]
+        );
+
+        let dir = std::env::temp_dir().join(format!("whisper-rs-batch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input.wav");
+        let output = dir.join("input.txt");
+        fs::write(&input, b"").unwrap();
+        assert!(!up_to_date(&input, std::slice::from_ref(&output)));
+        write_atomically(&output, |w| w.write_all(b"text")).unwrap();
+        assert!(up_to_date(&input, std::slice::from_ref(&output)));
+        assert_eq!(fs::read(&output).unwrap(), b"text");
+        assert!(!up_to_date(&dir.join("missing.wav"), &[output]));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn colliding_outputs() {
+        let runner = BatchRunner {
+            output_dir: Some(PathBuf::from("out")),
+            ..Default::default()
+        };
+        let inputs = [
+            PathBuf::from("a/talk.wav"),
+            PathBuf::from("b/talk.mp3"),
+            PathBuf::from("a/other.wav"),
+            PathBuf::from("talk.flac"),
+        ];
+        assert_eq!(
+            runner.collisions(&inputs),
+            [
+                Some(PathBuf::from("b/talk.mp3")),
+                Some(PathBuf::from("a/talk.wav")),
+                None,
+                Some(PathBuf::from("a/talk.wav")),
+            ]
+        );
+
+        // next to the inputs, only the same name in the same directory collides
+        let runner = BatchRunner::default();
+        assert_eq!(runner.collisions(&inputs[..3]), [None, None, None]);
+    }
+}
@@ -0,0 +1,165 @@
+//! Safe wrappers around the whisper.cpp micro-benchmarks.
+//!
+//! whisper.cpp only prints the results of its benchmarks, so the wrappers capture and parse that output.
+//! Capturing is only supported on Unix, elsewhere nothing can be parsed.
+
+use crate::capture::{self, CapturedLine};
+use crate::error::WhisperError;
+use std::ffi::c_int;
+
+/// Result of [bench_memcpy].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemcpyBench {
+    /// Measured memory bandwidth in GB/s, if it could be parsed from the output.
+    /// Always None on platforms other than Unix.
+    pub gb_per_sec: Option<f64>,
+    /// Everything whisper.cpp printed during the benchmark.
+    pub output: Vec<CapturedLine>,
+}
+
+/// A single matrix size measured by [bench_ggml_mul_mat].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MulMatBench {
+    /// The matrices are `size` x `size`.
+    pub size: usize,
+    /// Throughput per tensor type, e.g. `("F16", 120.5)`, in GFLOPS.
+    pub gflops: Vec<(String, f64)>,
+}
+
+/// Result of [bench_ggml_mul_mat].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MulMatBenchReport {
+    /// One entry per matrix size, in the order whisper.cpp ran them.
+    /// Always empty on platforms other than Unix.
+    pub sizes: Vec<MulMatBench>,
+    /// Everything whisper.cpp printed during the benchmark.
+    pub output: Vec<CapturedLine>,
+}
+
+/// Measure memory bandwidth by copying a large buffer.
+///
+/// whisper.cpp only prints the results. Unless an [OutputCapture](crate::OutputCapture) is installed,
+/// this installs one while the benchmark runs, which also captures what other threads print to stderr meanwhile.
+///
+/// # Arguments
+/// * n_threads: How many threads to use. Must be at least 1, returns an error otherwise.
+///
+/// # Returns
+/// Ok([MemcpyBench]) on success, Err(WhisperError) on failure.
+///
+/// # C++ equivalent
+/// `int whisper_bench_memcpy(int n_threads)`
+pub fn bench_memcpy(n_threads: usize) -> Result<MemcpyBench, WhisperError> {
+    if n_threads < 1 {
+        return Err(WhisperError::InvalidThreadCount);
+    }
+    let (ret, output) =
+        capture::collect(|| unsafe { whisper_rs_sys::whisper_bench_memcpy(n_threads as c_int) });
+    if ret != 0 {
+        return Err(WhisperError::GenericError(ret));
+    }
+    Ok(MemcpyBench {
+        gb_per_sec: parse_memcpy(&output),
+        output,
+    })
+}
+
+/// Measure ggml matrix multiplication throughput over a range of matrix sizes and tensor types.
+///
+/// whisper.cpp only prints the results. Unless an [OutputCapture](crate::OutputCapture) is installed,
+/// this installs one while the benchmark runs, which also captures what other threads print to stderr meanwhile.
+///
+/// # Arguments
+/// * n_threads: How many threads to use. Must be at least 1, returns an error otherwise.
+///
+/// # Returns
+/// Ok([MulMatBenchReport]) on success, Err(WhisperError) on failure.
+///
+/// # C++ equivalent
+/// `int whisper_bench_ggml_mul_mat(int n_threads)`
+pub fn bench_ggml_mul_mat(n_threads: usize) -> Result<MulMatBenchReport, WhisperError> {
+    if n_threads < 1 {
+        return Err(WhisperError::InvalidThreadCount);
+    }
+    let (ret, output) = capture::collect(|| unsafe {
+        whisper_rs_sys::whisper_bench_ggml_mul_mat(n_threads as c_int)
+    });
+    if ret != 0 {
+        return Err(WhisperError::GenericError(ret));
+    }
+    Ok(MulMatBenchReport {
+        sizes: output
+            .iter()
+            .filter_map(|line| parse_mul_mat(&line.text))
+            .collect(),
+        output,
+    })
+}
+
+/// Parse `memcpy: 12.34 GB/s`, preferring the last measurement that isn't a heat-up run.
+fn parse_memcpy(output: &[CapturedLine]) -> Option<f64> {
+    let measurements: Vec<(bool, f64)> = output
+        .iter()
+        .filter_map(|line| {
+            let rest = line.text.trim().strip_prefix("memcpy:")?;
+            let value = rest.split_whitespace().next()?.parse().ok()?;
+            Some((rest.contains("heat-up"), value))
+        })
+        .collect();
+    measurements
+        .iter()
+        .rev()
+        .find(|(heat_up, _)| !heat_up)
+        .or(measurements.last())
+        .map(|(_, value)| *value)
+}
+
+/// Parse `  64 x   64: F16    12.3 GFLOPS (100 runs) / F32    10.1 GFLOPS (100 runs)`.
+fn parse_mul_mat(line: &str) -> Option<MulMatBench> {
+    let (dims, results) = line.split_once(':')?;
+    let (n, m) = dims.split_once('x')?;
+    let size: usize = n.trim().parse().ok()?;
+    if m.trim().parse::<usize>().ok()? != size {
+        return None;
+    }
+    let words: Vec<&str> = results.split_whitespace().collect();
+    let gflops: Vec<(String, f64)> = words
+        .windows(3)
+        .filter(|w| w[2] == "GFLOPS")
+        .filter_map(|w| Some((w[0].to_string(), w[1].parse().ok()?)))
+        .collect();
+    (!gflops.is_empty()).then_some(MulMatBench { size, gflops })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::CaptureLevel;
+
+    #[test]
+    fn parse_output() {
+        let line = |text: &str| CapturedLine {
+            level: CaptureLevel::Info,
+            text: text.to_string(),
+        };
+        assert_eq!(
+            parse_memcpy(&[
+                line("memcpy:    9.50 GB/s (heat-up)"),
+                line("memcpy:   11.25 GB/s ( 1 thread)"),
+            ]),
+            Some(11.25)
+        );
+        assert_eq!(parse_memcpy(&[line("memcpy: 8.00 GB/s")]), Some(8.0));
+
+        let bench = parse_mul_mat(
+            "  64 x   64: F16     12.5 GFLOPS (128 runs) / F32     10.0 GFLOPS (128 runs)",
+        )
+        .unwrap();
+        assert_eq!(bench.size, 64);
+        assert_eq!(
+            bench.gflops,
+            vec![("F16".to_string(), 12.5), ("F32".to_string(), 10.0)]
+        );
+        assert_eq!(parse_mul_mat("whisper_bench: running"), None);
+    }
+}
@@ -0,0 +1,264 @@
+#![allow(clippy::uninlined_format_args)]
+
+//! Benchmark whisper.cpp on this machine across thread counts.
+
+use clap::Parser;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+use whisper_rs::{
+    bench_ggml_mul_mat, bench_memcpy, json_number, json_string, print_system_info, FullParams,
+    MulMatBench, SamplingStrategy, Timings, WhisperContext, WhisperError, WHISPER_SAMPLE_RATE,
+};
+
+/// Benchmark whisper.cpp on this machine across thread counts.
+///
+/// Without a model only the memcpy and matrix multiplication benchmarks run.
+#[derive(Parser, Debug)]
+#[command(name = "whisper-rs-bench", version)]
+struct Args {
+    /// Path to a ggml model file, to also benchmark encode and full.
+    #[arg(short, long)]
+    model: Option<PathBuf>,
+
+    /// Thread counts to run with, comma separated.
+    #[arg(
+        short,
+        long,
+        value_delimiter = ',',
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        default_values_t = default_threads()
+    )]
+    threads: Vec<usize>,
+
+    /// Seconds of synthetic audio to transcribe.
+    #[arg(short, long, default_value_t = 10)]
+    seconds: usize,
+
+    /// Output format.
+    #[arg(short, long, value_enum, default_value_t = Format::Table)]
+    format: Format,
+}
+
+#[derive(clap::ValueEnum, Copy, Clone, Debug, PartialEq)]
+enum Format {
+    Table,
+    Json,
+}
+
+/// Everything measured with one thread count.
+struct Run {
+    threads: usize,
+    memcpy_gb_per_sec: Option<f64>,
+    mul_mat: Vec<MulMatBench>,
+    encode: Option<Duration>,
+    full: Option<Timings>,
+}
+
+/// Powers of two up to 8, as far as the machine has cores.
+fn default_threads() -> Vec<usize> {
+    let max_threads = std::thread::available_parallelism().map_or(4, |n| n.get());
+    [1, 2, 4, 8]
+        .into_iter()
+        .filter(|&n| n <= max_threads)
+        .collect()
+}
+
+/// Deterministic low-level noise, so the decoder has something to chew on without an input file.
+fn synthetic_audio(seconds: usize) -> Vec<f32> {
+    let mut state: u32 = 0x2545_f491;
+    (0..seconds * WHISPER_SAMPLE_RATE)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            (state as f32 / u32::MAX as f32 - 0.5) * 0.02
+        })
+        .collect()
+}
+
+fn bench_model(
+    ctx: &mut WhisperContext,
+    audio: &[f32],
+    threads: usize,
+) -> Result<(Duration, Timings), WhisperError> {
+    // encode a single 30 second window
+    let window = &audio[..audio.len().min(30 * WHISPER_SAMPLE_RATE)];
+    ctx.pcm_to_mel(window, threads)?;
+    let start = Instant::now();
+    ctx.encode(0, threads)?;
+    let encode = start.elapsed();
+
+    let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+    params.set_n_threads(threads as _);
+    params.set_print_special(false);
+    params.set_print_progress(false);
+    params.set_print_realtime(false);
+    params.set_print_timestamps(false);
+    let timings = ctx.full(params, audio)?;
+    Ok((encode, timings))
+}
+
+fn run(args: &Args, progress: &mut dyn Write) -> Result<Vec<Run>, WhisperError> {
+    let mut ctx = match &args.model {
+        Some(path) => Some(WhisperContext::new(&path.to_string_lossy())?),
+        None => None,
+    };
+    let audio = synthetic_audio(args.seconds);
+    let mut runs = Vec::new();
+    for &threads in &args.threads {
+        if args.format == Format::Table {
+            let _ = writeln!(progress, "running with {} threads...", threads);
+        }
+        let (encode, full) = match ctx.as_mut() {
+            Some(ctx) => {
+                let (encode, full) = bench_model(ctx, &audio, threads)?;
+                (Some(encode), Some(full))
+            }
+            None => (None, None),
+        };
+        runs.push(Run {
+            threads,
+            memcpy_gb_per_sec: bench_memcpy(threads)?.gb_per_sec,
+            mul_mat: bench_ggml_mul_mat(threads)?.sizes,
+            encode,
+            full,
+        });
+    }
+    Ok(runs)
+}
+
+/// Best throughput of any tensor type at the largest matrix size.
+fn peak_gflops(mul_mat: &[MulMatBench]) -> Option<f64> {
+    mul_mat
+        .iter()
+        .max_by_key(|b| b.size)?
+        .gflops
+        .iter()
+        .map(|(_, gflops)| *gflops)
+        .reduce(f64::max)
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn print_table(runs: &[Run]) {
+    let cell = |value: Option<f64>, precision: usize| {
+        value.map_or("-".to_string(), |v| format!("{:.*}", precision, v))
+    };
+    println!(
+        "{:>7} | {:>11} | {:>14} | {:>9} | {:>9} | {:>6}",
+        "threads", "memcpy GB/s", "mul_mat GFLOPS", "encode ms", "full ms", "RTF"
+    );
+    println!("{}", "-".repeat(73));
+    for run in runs {
+        println!(
+            "{:>7} | {:>11} | {:>14} | {:>9} | {:>9} | {:>6}",
+            run.threads,
+            cell(run.memcpy_gb_per_sec, 2),
+            cell(peak_gflops(&run.mul_mat), 1),
+            cell(run.encode.map(millis), 1),
+            cell(run.full.map(|t| millis(t.total)), 1),
+            cell(run.full.map(|t| t.real_time_factor()), 3),
+        );
+    }
+}
+
+fn print_json(args: &Args, runs: &[Run]) {
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "{{\"system_info\":{},\"model\":{},\"audio_seconds\":{},\"runs\":[",
+        json_string(print_system_info().trim()),
+        args.model
+            .as_ref()
+            .map_or("null".to_string(), |path| json_string(
+                &path.to_string_lossy()
+            )),
+        args.seconds
+    );
+    for (i, run) in runs.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let mul_mat: Vec<String> = run
+            .mul_mat
+            .iter()
+            .map(|b| {
+                let gflops: Vec<String> = b
+                    .gflops
+                    .iter()
+                    .map(|(ty, v)| format!("{}:{}", json_string(ty), json_number(Some(*v))))
+                    .collect();
+                format!(
+                    "{{\"size\":{},\"gflops\":{{{}}}}}",
+                    b.size,
+                    gflops.join(",")
+                )
+            })
+            .collect();
+        let full = run.full.map_or("null".to_string(), |t| {
+            format!(
+                "{{\"load_ms\":{},\"mel_ms\":{},\"encode_ms\":{},\"decode_ms\":{},\"total_ms\":{},\"windows\":{},\"real_time_factor\":{}}}",
+                json_number(Some(millis(t.load))),
+                json_number(Some(millis(t.mel))),
+                json_number(t.encode.map(millis)),
+                json_number(t.decode.map(millis)),
+                json_number(Some(millis(t.total))),
+                t.n_windows,
+                json_number(Some(t.real_time_factor())),
+            )
+        });
+        let _ = write!(
+            out,
+            "{{\"threads\":{},\"memcpy_gb_per_sec\":{},\"mul_mat\":[{}],\"encode_ms\":{},\"full\":{}}}",
+            run.threads,
+            json_number(run.memcpy_gb_per_sec),
+            mul_mat.join(","),
+            json_number(run.encode.map(millis)),
+            full
+        );
+    }
+    out.push_str("]}");
+    println!("{}", out);
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    // whisper.cpp prints the benchmark results and timings, read them back instead of mixing them into the report
+    #[cfg(unix)]
+    let capture = whisper_rs::OutputCapture::install(whisper_rs::CaptureConfig {
+        stdout: false,
+        ..Default::default()
+    })
+    .ok();
+    #[cfg(unix)]
+    let mut progress: Box<dyn Write> = match capture.as_ref().map(|c| c.original_stderr()) {
+        Some(Ok(stderr)) => Box::new(stderr),
+        _ => Box::new(io::stderr()),
+    };
+    #[cfg(not(unix))]
+    let mut progress: Box<dyn Write> = Box::new(io::stderr());
+
+    let result = run(&args, &mut progress);
+    drop(progress);
+    #[cfg(unix)]
+    drop(capture);
+    match result {
+        Ok(runs) => {
+            match args.format {
+                Format::Table => print_table(&runs),
+                Format::Json => print_json(&args, &runs),
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
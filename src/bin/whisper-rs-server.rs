@@ -0,0 +1,695 @@
+#![allow(clippy::uninlined_format_args)]
+
+//! HTTP transcription server with an OpenAI compatible API.
+//!
+//! Serves `POST /v1/audio/transcriptions`, `POST /v1/audio/translations` and `GET /health`.
+//! The model is loaded once, and requests are run one at a time through a bounded queue.
+//! The HTTP threads only parse requests and queue them, the worker running the model responds.
+
+use clap::Parser;
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::time::Instant;
+use tiny_http::{Header, Method, Request, Response, Server};
+use whisper_rs::{
+    compression_ratio, decode_wav, is_wav, json_number, json_string, FullParams, OutputFormat,
+    SamplingStrategy, Segment, WhisperContext, WhisperError, WHISPER_SAMPLE_RATE,
+};
+
+/// Serve whisper.cpp transcriptions over an OpenAI compatible HTTP API.
+#[derive(Parser, Debug)]
+#[command(name = "whisper-rs-server", version)]
+struct Args {
+    /// Path to the ggml model file.
+    #[arg(short, long)]
+    model: PathBuf,
+
+    /// Address to listen on.
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+
+    /// Port to listen on.
+    #[arg(short, long, default_value_t = 8080)]
+    port: u16,
+
+    /// Number of threads per transcription.
+    #[arg(short, long, default_value_t = std::thread::available_parallelism().map_or(4, |n| n.get().min(8)))]
+    threads: usize,
+
+    /// Maximum number of requests waiting for the model. Further requests get a 503.
+    #[arg(long, default_value_t = 16)]
+    queue_size: usize,
+
+    /// Maximum upload size in megabytes.
+    #[arg(long, default_value_t = 25)]
+    max_upload_mb: usize,
+
+    /// Number of threads accepting and parsing HTTP requests.
+    #[arg(long, default_value_t = 4)]
+    http_threads: usize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum ResponseFormat {
+    Json,
+    Text,
+    Srt,
+    Vtt,
+    VerboseJson,
+}
+
+impl ResponseFormat {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "json" => Some(Self::Json),
+            "text" => Some(Self::Text),
+            "srt" => Some(Self::Srt),
+            "vtt" => Some(Self::Vtt),
+            "verbose_json" => Some(Self::VerboseJson),
+            _ => None,
+        }
+    }
+}
+
+/// A parsed transcription request.
+struct Transcription {
+    audio: Vec<f32>,
+    translate: bool,
+    language: Option<String>,
+    prompt: Option<String>,
+    temperature: f32,
+    format: ResponseFormat,
+}
+
+/// A transcription waiting for the model, responded to by the worker.
+struct Job {
+    request: Request,
+    start: Instant,
+    transcription: Transcription,
+}
+
+/// Shared between the HTTP threads and the worker.
+struct State {
+    queue: SyncSender<Job>,
+    queue_size: usize,
+    /// Number of jobs queued or running.
+    pending: AtomicUsize,
+    max_upload: usize,
+}
+
+/// An error response in the OpenAI format.
+struct ApiError {
+    status: u16,
+    message: String,
+    param: Option<&'static str>,
+}
+
+impl ApiError {
+    fn invalid(message: impl Into<String>, param: Option<&'static str>) -> Self {
+        Self {
+            status: 400,
+            message: message.into(),
+            param,
+        }
+    }
+
+    /// Only the root cause is returned to the client: the full error holds whisper.cpp's output,
+    /// which may come from other requests.
+    fn from_whisper(e: &WhisperError) -> Self {
+        let cause = e.root_cause();
+        Self {
+            status: match cause {
+                WhisperError::InvalidText | WhisperError::NullByteInString { .. } => 400,
+                _ => 500,
+            },
+            message: cause.to_string(),
+            param: None,
+        }
+    }
+
+    fn into_response(self) -> Response<std::io::Cursor<Vec<u8>>> {
+        let kind = match self.status {
+            400..=499 => "invalid_request_error",
+            503 => "server_overloaded",
+            _ => "server_error",
+        };
+        let body = format!(
+            "{{\"error\":{{\"message\":{},\"type\":{},\"param\":{},\"code\":null}}}}",
+            json_string(&self.message),
+            json_string(kind),
+            self.param.map_or("null".to_string(), json_string)
+        );
+        respond(self.status, OutputFormat::Json.mime_type(), body)
+    }
+}
+
+fn respond(status: u16, content_type: &str, body: String) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_string(body)
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", content_type).expect("valid header"))
+}
+
+/// A single part of a `multipart/form-data` body.
+#[derive(Debug, PartialEq)]
+struct Part<'a> {
+    name: String,
+    filename: Option<String>,
+    data: &'a [u8],
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Get a parameter like `boundary=...` or `name="..."` out of a header value.
+fn header_param(value: &str, key: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|param| {
+        let (k, v) = param.trim().split_once('=')?;
+        k.trim()
+            .eq_ignore_ascii_case(key)
+            .then(|| v.trim().trim_matches('"').to_string())
+    })
+}
+
+fn parse_multipart<'a>(body: &'a [u8], boundary: &str) -> Result<Vec<Part<'a>>, String> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let mut rest = match find(body, delimiter.as_bytes()) {
+        Some(start) => &body[start + delimiter.len()..],
+        None => return Err("multipart boundary not found".to_string()),
+    };
+    // every delimiter is followed by CRLF, or -- after the last part
+    while let Some(part) = rest.strip_prefix(b"\r\n") {
+        let headers_end = find(part, b"\r\n\r\n").ok_or("unterminated part headers")?;
+        let headers = String::from_utf8_lossy(&part[..headers_end]);
+        let content = &part[headers_end + 4..];
+        let end = find(content, format!("\r\n{}", delimiter).as_bytes())
+            .ok_or("unterminated multipart body")?;
+
+        let disposition = headers
+            .lines()
+            .find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.trim()
+                    .eq_ignore_ascii_case("content-disposition")
+                    .then_some(value)
+            })
+            .ok_or("part without a Content-Disposition header")?;
+        parts.push(Part {
+            name: header_param(disposition, "name").ok_or("part without a name")?,
+            filename: header_param(disposition, "filename"),
+            data: &content[..end],
+        });
+        rest = &content[end + 2 + delimiter.len()..];
+    }
+    Ok(parts)
+}
+
+fn content_type(request: &Request) -> Option<String> {
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Content-Type"))
+        .map(|h| h.value.as_str().to_string())
+}
+
+fn parse_transcription(
+    request: &mut Request,
+    state: &State,
+    translate: bool,
+) -> Result<Transcription, ApiError> {
+    let boundary = content_type(request)
+        .filter(|ct| ct.to_ascii_lowercase().starts_with("multipart/form-data"))
+        .and_then(|ct| header_param(&ct, "boundary"))
+        .ok_or_else(|| ApiError::invalid("expected a multipart/form-data body", None))?;
+    if request.body_length().unwrap_or(0) > state.max_upload {
+        return Err(ApiError {
+            status: 413,
+            message: format!("uploads are limited to {} bytes", state.max_upload),
+            param: Some("file"),
+        });
+    }
+    let mut body = Vec::new();
+    request
+        .as_reader()
+        .take(state.max_upload as u64 + 1)
+        .read_to_end(&mut body)
+        .map_err(|e| ApiError::invalid(format!("failed to read the body: {}", e), None))?;
+    if body.len() > state.max_upload {
+        return Err(ApiError {
+            status: 413,
+            message: format!("uploads are limited to {} bytes", state.max_upload),
+            param: Some("file"),
+        });
+    }
+    let parts = parse_multipart(&body, &boundary).map_err(|e| ApiError::invalid(e, None))?;
+    let field = |name: &str| {
+        parts
+            .iter()
+            .find(|p| p.name == name)
+            .map(|p| String::from_utf8_lossy(p.data).trim().to_string())
+            .filter(|v| !v.is_empty())
+    };
+
+    let file = parts
+        .iter()
+        .find(|p| p.name == "file")
+        .ok_or_else(|| ApiError::invalid("missing the file field", Some("file")))?;
+    if !is_wav(file.data) {
+        return Err(ApiError::invalid(
+            format!(
+                "{} is not a WAV file; only 16 kHz WAV audio is supported",
+                file.filename.as_deref().unwrap_or("the upload")
+            ),
+            Some("file"),
+        ));
+    }
+    let samples =
+        decode_wav(file.data).map_err(|e| ApiError::invalid(e.to_string(), Some("file")))?;
+    let format = match field("response_format") {
+        Some(f) => ResponseFormat::parse(&f).ok_or_else(|| {
+            ApiError::invalid(
+                format!("unsupported response_format {:?}", f),
+                Some("response_format"),
+            )
+        })?,
+        None => ResponseFormat::Json,
+    };
+    let temperature = match field("temperature") {
+        Some(t) => t
+            .parse::<f32>()
+            .ok()
+            .filter(|t| (0.0..=1.0).contains(t))
+            .ok_or_else(|| {
+                ApiError::invalid("temperature must be between 0 and 1", Some("temperature"))
+            })?,
+        None => 0.0,
+    };
+    Ok(Transcription {
+        audio: samples,
+        translate,
+        // translations are always into English, the spoken language is detected
+        language: if translate { None } else { field("language") },
+        prompt: field("prompt"),
+        temperature,
+        format,
+    })
+}
+
+fn render(
+    transcription: &Transcription,
+    segments: &[Segment],
+) -> Response<std::io::Cursor<Vec<u8>>> {
+    let text: String = segments.iter().map(|s| s.text.as_str()).collect();
+    match transcription.format {
+        ResponseFormat::Json => respond(
+            200,
+            OutputFormat::Json.mime_type(),
+            format!("{{\"text\":{}}}", json_string(text.trim())),
+        ),
+        ResponseFormat::Text => respond(
+            200,
+            OutputFormat::Txt.mime_type(),
+            format!("{}\n", text.trim()),
+        ),
+        ResponseFormat::Srt => respond(
+            200,
+            OutputFormat::Srt.mime_type(),
+            OutputFormat::Srt.render(segments),
+        ),
+        ResponseFormat::Vtt => respond(
+            200,
+            OutputFormat::Vtt.mime_type(),
+            OutputFormat::Vtt.render(segments),
+        ),
+        ResponseFormat::VerboseJson => respond(
+            200,
+            OutputFormat::Json.mime_type(),
+            verbose_json(
+                segments,
+                transcription.translate,
+                transcription.language.as_deref(),
+                transcription.audio.len() as f64 / WHISPER_SAMPLE_RATE as f64,
+                transcription.temperature,
+            ),
+        ),
+    }
+}
+
+/// The `verbose_json` response. `language` is only included if the client gave it,
+/// since whisper.cpp doesn't report the language it detected.
+fn verbose_json(
+    segments: &[Segment],
+    translate: bool,
+    language: Option<&str>,
+    duration: f64,
+    temperature: f32,
+) -> String {
+    let text: String = segments.iter().map(|s| s.text.as_str()).collect();
+    let segments: Vec<String> = segments
+        .iter()
+        .enumerate()
+        .map(|(i, segment)| {
+            let tokens: Vec<String> = segment
+                .tokens
+                .iter()
+                .filter(|t| t.kind.is_text())
+                .map(|t| t.id.to_string())
+                .collect();
+            let confidence = segment.confidence(0.5);
+            format!(
+                "{{\"id\":{},\"start\":{},\"end\":{},\"text\":{},\"tokens\":[{}],\"temperature\":{},\"avg_logprob\":{},\"compression_ratio\":{},\"no_speech_prob\":null}}",
+                i,
+                segment.t0.as_secs_f64(),
+                segment.t1.as_secs_f64(),
+                json_string(&segment.text),
+                tokens.join(","),
+                json_number(Some(temperature as f64)),
+                json_number(Some(confidence.avg_logprob as f64)),
+                json_number(Some(compression_ratio(&segment.text) as f64)),
+            )
+        })
+        .collect();
+    format!(
+        "{{\"task\":{},{}\"duration\":{},\"text\":{},\"segments\":[{}]}}",
+        json_string(if translate { "translate" } else { "transcribe" }),
+        language.map_or(String::new(), |language| format!(
+            "\"language\":{},",
+            json_string(language)
+        )),
+        json_number(Some(duration)),
+        json_string(text.trim()),
+        segments.join(",")
+    )
+}
+
+fn handle(mut request: Request, state: &State) {
+    let start = Instant::now();
+    let method = request.method().clone();
+    let path = request.url().split('?').next().unwrap_or("").to_string();
+    let response = match (&method, path.as_str()) {
+        (Method::Get, "/health") => Ok(respond(
+            200,
+            OutputFormat::Json.mime_type(),
+            format!(
+                "{{\"status\":\"ok\",\"pending\":{},\"queue_size\":{}}}",
+                state.pending.load(Ordering::SeqCst),
+                state.queue_size
+            ),
+        )),
+        (Method::Post, "/v1/audio/transcriptions" | "/v1/audio/translations") => {
+            let translate = path == "/v1/audio/translations";
+            match parse_transcription(&mut request, state, translate) {
+                Ok(transcription) => {
+                    return enqueue(
+                        Job {
+                            request,
+                            start,
+                            transcription,
+                        },
+                        state,
+                    )
+                }
+                Err(e) => Err(e),
+            }
+        }
+        (_, "/health" | "/v1/audio/transcriptions" | "/v1/audio/translations") => Err(ApiError {
+            status: 405,
+            message: format!("{} is not allowed on {}", method, path),
+            param: None,
+        }),
+        _ => Err(ApiError {
+            status: 404,
+            message: format!("unknown endpoint {}", path),
+            param: None,
+        }),
+    };
+    finish(
+        request,
+        response.unwrap_or_else(ApiError::into_response),
+        start,
+    );
+}
+
+/// Queue a job for the worker, or respond with a 503 if the queue is full.
+fn enqueue(job: Job, state: &State) {
+    state.pending.fetch_add(1, Ordering::SeqCst);
+    if let Err(e) = state.queue.try_send(job) {
+        state.pending.fetch_sub(1, Ordering::SeqCst);
+        let (job, message) = match e {
+            TrySendError::Full(job) => (job, "too many requests are queued, try again later"),
+            TrySendError::Disconnected(job) => (job, "the transcription worker has stopped"),
+        };
+        let response = ApiError {
+            status: 503,
+            message: message.to_string(),
+            param: None,
+        }
+        .into_response();
+        finish(job.request, response, job.start);
+    }
+}
+
+/// Send the response and log the request.
+fn finish(request: Request, response: Response<std::io::Cursor<Vec<u8>>>, start: Instant) {
+    eprintln!(
+        "{} {} {} {:.2}s",
+        request.method(),
+        request.url().split('?').next().unwrap_or(""),
+        response.status_code().0,
+        start.elapsed().as_secs_f64()
+    );
+    if let Err(e) = request.respond(response) {
+        eprintln!("failed to send the response: {}", e);
+    }
+}
+
+/// Start the threads accepting and parsing HTTP requests.
+fn spawn_handlers(
+    server: &Arc<Server>,
+    state: &Arc<State>,
+    n: usize,
+) -> Vec<std::thread::JoinHandle<()>> {
+    (0..n.max(1))
+        .map(|_| {
+            let server = Arc::clone(server);
+            let state = Arc::clone(state);
+            std::thread::spawn(move || {
+                while let Ok(request) = server.recv() {
+                    handle(request, &state);
+                }
+            })
+        })
+        .collect()
+}
+
+/// Owns the context and runs queued jobs one at a time.
+fn worker(mut ctx: WhisperContext, jobs: Receiver<Job>, threads: usize, state: Arc<State>) {
+    for job in jobs {
+        let response = match transcribe(&mut ctx, &job.transcription, threads) {
+            Ok(segments) => render(&job.transcription, &segments),
+            Err(e) => {
+                eprintln!("transcription failed: {}", e);
+                ApiError::from_whisper(&e).into_response()
+            }
+        };
+        state.pending.fetch_sub(1, Ordering::SeqCst);
+        finish(job.request, response, job.start);
+    }
+}
+
+fn transcribe(
+    ctx: &mut WhisperContext,
+    transcription: &Transcription,
+    threads: usize,
+) -> Result<Vec<Segment>, WhisperError> {
+    let prompt_tokens = match &transcription.prompt {
+        Some(prompt) => ctx.tokenize(prompt)?,
+        None => Vec::new(),
+    };
+    let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+    params.set_n_threads(threads as _);
+    params.set_translate(transcription.translate);
+    params.set_language(Some(transcription.language.as_deref().unwrap_or("auto")));
+    params.set_temperature(transcription.temperature);
+    params.set_tokens(&prompt_tokens);
+    params.set_print_special(false);
+    params.set_print_progress(false);
+    params.set_print_realtime(false);
+    params.set_print_timestamps(false);
+    ctx.full(params, &transcription.audio)?;
+    ctx.full_get_segments()
+}
+
+fn main() {
+    let args = Args::parse();
+
+    // attach whisper.cpp's output to errors, while still showing it
+    #[cfg(unix)]
+    let _capture = whisper_rs::OutputCapture::install(whisper_rs::CaptureConfig {
+        stdout: false,
+        passthrough: true,
+        ..Default::default()
+    })
+    .ok();
+
+    let ctx = match WhisperContext::new(&args.model.to_string_lossy()) {
+        Ok(ctx) => ctx,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let server = match Server::http((args.host.as_str(), args.port)) {
+        Ok(server) => Arc::new(server),
+        Err(e) => {
+            eprintln!(
+                "error: failed to listen on {}:{}: {}",
+                args.host, args.port, e
+            );
+            std::process::exit(1);
+        }
+    };
+
+    let (queue, jobs) = mpsc::sync_channel(args.queue_size);
+    let state = Arc::new(State {
+        queue,
+        queue_size: args.queue_size,
+        pending: AtomicUsize::new(0),
+        max_upload: args.max_upload_mb * 1024 * 1024,
+    });
+    let worker_state = Arc::clone(&state);
+    let threads = args.threads;
+    let worker = std::thread::spawn(move || worker(ctx, jobs, threads, worker_state));
+
+    eprintln!("listening on http://{}:{}", args.host, args.port);
+    let handlers = spawn_handlers(&server, &state, args.http_threads);
+    for handler in handlers {
+        let _ = handler.join();
+    }
+    drop(state);
+    let _ = worker.join();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn multipart() {
+        let body = b"--XyZ\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"a.wav\"\r\n\
+            Content-Type: audio/wav\r\n\r\n\
+            RIFF\r\n--data\r\n\
+            --XyZ\r\n\
+            Content-Disposition: form-data; name=\"language\"\r\n\r\n\
+            de\r\n\
+            --XyZ--\r\n";
+        let parts = parse_multipart(body, "XyZ").unwrap();
+        assert_eq!(
+            parts,
+            vec![
+                Part {
+                    name: "file".to_string(),
+                    filename: Some("a.wav".to_string()),
+                    data: b"RIFF\r\n--data",
+                },
+                Part {
+                    name: "language".to_string(),
+                    filename: None,
+                    data: b"de",
+                },
+            ]
+        );
+        assert_eq!(
+            header_param("multipart/form-data; boundary=XyZ", "boundary"),
+            Some("XyZ".to_string())
+        );
+        assert!(parse_multipart(b"garbage", "XyZ").is_err());
+    }
+
+    #[test]
+    fn error_hides_output() {
+        let error = WhisperError::Operation {
+            operation: whisper_rs::Operation::Full {
+                n_samples: 16000,
+                threads: 4,
+            },
+            error: Box::new(WhisperError::FailedToEncode),
+            output: vec![whisper_rs::CapturedLine {
+                level: whisper_rs::CaptureLevel::Error,
+                text: "whisper_full: failed to encode (another request's log line)".to_string(),
+            }],
+        };
+        let api_error = ApiError::from_whisper(&error);
+        assert_eq!(api_error.status, 500);
+        assert_eq!(api_error.message, "failed to run the encoder");
+    }
+
+    #[test]
+    fn full_queue_is_rejected() {
+        use std::io::Write;
+
+        let server = Arc::new(Server::http("127.0.0.1:0").unwrap());
+        let addr = server.server_addr().to_ip().unwrap();
+        let queue_size = 2;
+        // nothing takes jobs off the queue, as if the worker was busy
+        let (queue, _jobs) = mpsc::sync_channel(queue_size);
+        let state = Arc::new(State {
+            queue,
+            queue_size,
+            pending: AtomicUsize::new(0),
+            max_upload: 1024 * 1024,
+        });
+        spawn_handlers(&server, &state, 1);
+
+        let mut wav = std::io::Cursor::new(Vec::new());
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: WHISPER_SAMPLE_RATE as u32,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::new(&mut wav, spec).unwrap();
+        for _ in 0..1600 {
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+        let mut body =
+            b"--XyZ\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.wav\"\r\n\r\n"
+                .to_vec();
+        body.extend_from_slice(wav.get_ref());
+        body.extend_from_slice(b"\r\n--XyZ--\r\n");
+        let send = || {
+            let mut stream = std::net::TcpStream::connect(addr).unwrap();
+            stream
+                .set_read_timeout(Some(std::time::Duration::from_secs(10)))
+                .unwrap();
+            write!(
+                stream,
+                "POST /v1/audio/transcriptions HTTP/1.1\r\nHost: localhost\r\n\
+                Content-Type: multipart/form-data; boundary=XyZ\r\n\
+                Content-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            )
+            .unwrap();
+            stream.write_all(&body).unwrap();
+            stream
+        };
+
+        let mut queued = Vec::new();
+        for n in 1..=queue_size {
+            queued.push(send());
+            while state.pending.load(Ordering::SeqCst) < n {
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+        }
+        let mut response = String::new();
+        send().read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 503"), "{}", response);
+        assert_eq!(state.pending.load(Ordering::SeqCst), queue_size);
+    }
+}
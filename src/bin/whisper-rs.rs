@@ -0,0 +1,490 @@
+#![allow(clippy::uninlined_format_args)]
+
+//! Transcribe audio files from the command line.
+
+use clap::{CommandFactory, Parser};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Mutex;
+use whisper_rs::{
+    convert_f32le_to_float_audio, convert_s16le_to_float_audio, decode_wav, is_wav, BatchOutcome,
+    BatchRunner, ContextPool, FullParams, OutputFormat, SamplingStrategy, WhisperContext,
+    WhisperError,
+};
+
+const EXIT_CODES: &str = "\
+Exit codes:
+   0  success
+   1  other error
+   2  invalid arguments
+   3  failed to read the input audio
+   4  failed to write the output
+   5  some inputs of a batch failed
+  10  failed to load the model
+  11  failed to compute the spectrogram
+  12  failed to detect the language
+  13  failed to initialize the decoders
+  14  audio context too large for the model
+  15  failed to run the encoder
+  16  failed to run the decoder
+  17  invalid prompt text";
+
+/// Transcribe or translate speech with whisper.cpp.
+#[derive(Parser, Debug)]
+#[command(name = "whisper-rs", version, after_help = EXIT_CODES)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Path to the ggml model file.
+    #[arg(short, long)]
+    model: PathBuf,
+
+    /// Input audio: a WAV file, or raw 16 kHz mono PCM (see --pcm-format). Use - for stdin.
+    /// Required unless running a subcommand.
+    input: Option<PathBuf>,
+
+    /// Sample format of raw PCM input.
+    #[arg(long, value_enum, default_value_t = PcmFormat::S16le)]
+    pcm_format: PcmFormat,
+
+    /// Spoken language, or "auto" to detect it.
+    #[arg(short, long, default_value = "en")]
+    language: String,
+
+    /// Translate into English.
+    #[arg(long)]
+    translate: bool,
+
+    /// Number of threads to use.
+    #[arg(short, long, default_value_t = default_threads())]
+    threads: usize,
+
+    /// Use beam search with this many beams instead of greedy sampling.
+    #[arg(long)]
+    beam_size: Option<i32>,
+
+    /// Number of candidates to sample from when using greedy sampling with a temperature.
+    #[arg(long, default_value_t = 1)]
+    best_of: i32,
+
+    /// Initial sampling temperature.
+    #[arg(long, default_value_t = 0.0)]
+    temperature: f32,
+
+    /// Temperature increase when falling back after a failed decode.
+    #[arg(long)]
+    temperature_inc: Option<f32>,
+
+    /// Start transcribing at this offset into the audio, in milliseconds.
+    #[arg(long, default_value_t = 0)]
+    offset_ms: i32,
+
+    /// Only transcribe this much audio, in milliseconds. 0 means all of it.
+    #[arg(long, default_value_t = 0)]
+    duration_ms: i32,
+
+    /// Maximum segment length in characters. 0 means no limit.
+    #[arg(long, default_value_t = 0)]
+    max_len: i32,
+
+    /// Compute word-level timestamps (included in JSON output).
+    #[arg(long)]
+    word_timestamps: bool,
+
+    /// Text to prime the decoder with, such as names or vocabulary.
+    #[arg(long)]
+    prompt: Option<String>,
+
+    /// Output formats, comma separated.
+    #[arg(short = 'f', long, value_delimiter = ',', default_value = "txt")]
+    output_format: Vec<OutputFormat>,
+
+    /// Write each format to <OUTPUT>.<extension> instead of stdout. Batches use --output-dir instead.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Show whisper.cpp's own output on stderr.
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Transcribe many files, writing one output per input and format.
+    ///
+    /// Transcription options go before the subcommand. Inputs whose outputs are newer are skipped.
+    Batch(BatchArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct BatchArgs {
+    /// Input audio files.
+    inputs: Vec<PathBuf>,
+
+    /// Read more input paths from this file, one per line. Use - for stdin.
+    #[arg(long)]
+    list: Option<PathBuf>,
+
+    /// Write outputs to this directory instead of next to each input.
+    #[arg(long)]
+    output_dir: Option<PathBuf>,
+
+    /// Number of files to transcribe at the same time, each with its own copy of the model.
+    #[arg(short, long, default_value_t = 1)]
+    workers: usize,
+
+    /// Transcribe inputs even if their outputs are up to date.
+    #[arg(long)]
+    force: bool,
+}
+
+#[derive(clap::ValueEnum, Copy, Clone, Debug)]
+enum PcmFormat {
+    /// 16 bit signed little endian integers.
+    S16le,
+    /// 32 bit little endian floats.
+    F32le,
+}
+
+fn default_threads() -> usize {
+    std::thread::available_parallelism().map_or(4, |n| n.get().min(8))
+}
+
+/// Why the tool failed, mapped to the exit codes in [EXIT_CODES].
+enum Failure {
+    Input(String),
+    Output(io::Error),
+    Whisper(WhisperError),
+    Batch(usize),
+}
+
+impl Failure {
+    fn exit_code(&self) -> u8 {
+        match self {
+            Self::Input(_) => 3,
+            Self::Output(_) => 4,
+            Self::Batch(_) => 5,
+            Self::Whisper(e) => match e.root_cause() {
+                WhisperError::InitError => 10,
+                WhisperError::UnableToCalculateSpectrogram => 11,
+                WhisperError::FailedToDetectLanguage => 12,
+                WhisperError::FailedToInitializeDecoders => 13,
+                WhisperError::AudioCtxTooLarge => 14,
+                WhisperError::FailedToEncode => 15,
+                WhisperError::FailedToDecode => 16,
+                WhisperError::InvalidText | WhisperError::NullByteInString { .. } => 17,
+                _ => 1,
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Input(e) => write!(f, "failed to read input: {}", e),
+            Self::Output(e) => write!(f, "failed to write output: {}", e),
+            Self::Whisper(e) => write!(f, "{}", e),
+            Self::Batch(n) => write!(f, "{} inputs failed", n),
+        }
+    }
+}
+
+impl From<WhisperError> for Failure {
+    fn from(e: WhisperError) -> Self {
+        Self::Whisper(e)
+    }
+}
+
+fn read_audio(path: &Path, format: PcmFormat) -> Result<Vec<f32>, String> {
+    let mut bytes = Vec::new();
+    if path == Path::new("-") {
+        io::stdin().read_to_end(&mut bytes)
+    } else {
+        File::open(path).and_then(|f| BufReader::new(f).read_to_end(&mut bytes))
+    }
+    .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    if is_wav(&bytes) {
+        decode_wav(&bytes).map_err(|e| format!("{}: {}", path.display(), e))
+    } else {
+        Ok(match format {
+            PcmFormat::S16le => convert_s16le_to_float_audio(&bytes),
+            PcmFormat::F32le => convert_f32le_to_float_audio(&bytes),
+        })
+    }
+}
+
+fn prompt_tokens(args: &Args, ctx: &WhisperContext) -> Result<Vec<i32>, Failure> {
+    Ok(match &args.prompt {
+        Some(prompt) => ctx.tokenize(prompt)?,
+        None => Vec::new(),
+    })
+}
+
+fn full_params<'a>(args: &'a Args, prompt_tokens: &'a [i32]) -> FullParams<'a, 'a> {
+    let strategy = match args.beam_size {
+        Some(beam_size) => SamplingStrategy::BeamSearch {
+            beam_size,
+            patience: -1.0,
+        },
+        None => SamplingStrategy::Greedy {
+            best_of: args.best_of,
+        },
+    };
+    let mut params = FullParams::new(strategy);
+    params.set_n_threads(args.threads as _);
+    params.set_language(Some(&args.language));
+    params.set_translate(args.translate);
+    params.set_temperature(args.temperature);
+    if let Some(temperature_inc) = args.temperature_inc {
+        params.set_temperature_inc(temperature_inc);
+    }
+    params.set_offset_ms(args.offset_ms);
+    params.set_duration_ms(args.duration_ms);
+    params.set_max_len(args.max_len);
+    params.set_token_timestamps(args.word_timestamps);
+    params.set_tokens(prompt_tokens);
+    params.set_print_special(false);
+    params.set_print_progress(false);
+    params.set_print_realtime(false);
+    params.set_print_timestamps(false);
+    params
+}
+
+/// Where our own progress output goes: the original stderr, around the capture of whisper.cpp's output.
+type Log = Mutex<Box<dyn Write + Send>>;
+
+fn log(out: &Log, line: std::fmt::Arguments) {
+    let mut out = out.lock().unwrap_or_else(|e| e.into_inner());
+    let _ = writeln!(out, "{}", line);
+}
+
+fn run(args: &Args, input: &Path, stderr: &Log) -> Result<(), Failure> {
+    let audio = read_audio(input, args.pcm_format).map_err(Failure::Input)?;
+
+    let mut ctx = WhisperContext::new(&args.model.to_string_lossy())?;
+    let prompt_tokens = prompt_tokens(args, &ctx)?;
+    let params = full_params(args, &prompt_tokens);
+
+    let timings = ctx.full(params, &audio)?;
+    if args.verbose {
+        log(
+            stderr,
+            format_args!(
+                "transcribed {:.1} s of audio in {:.1} s (real-time factor {:.3})",
+                timings.audio.as_secs_f64(),
+                timings.total.as_secs_f64(),
+                timings.real_time_factor()
+            ),
+        );
+        if let (Some(encode), Some(decode)) = (timings.encode, timings.decode) {
+            log(
+                stderr,
+                format_args!(
+                    "encode {:.1} s, decode {:.1} s",
+                    encode.as_secs_f64(),
+                    decode.as_secs_f64()
+                ),
+            );
+        }
+    }
+    let segments = ctx.full_get_segments()?;
+
+    for &format in &args.output_format {
+        match &args.output {
+            Some(base) => {
+                let mut path = base.clone().into_os_string();
+                path.push(".");
+                path.push(format.extension());
+                File::create(&path)
+                    .and_then(|file| format.write(&segments, io::BufWriter::new(file)))
+                    .map_err(Failure::Output)?;
+            }
+            None => {
+                let mut stdout = io::stdout().lock();
+                format
+                    .write(&segments, &mut stdout)
+                    .and_then(|()| stdout.flush())
+                    .map_err(Failure::Output)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_batch(args: &Args, batch: &BatchArgs, stderr: &Log) -> Result<(), Failure> {
+    let mut inputs = batch.inputs.clone();
+    if let Some(list) = &batch.list {
+        let mut text = String::new();
+        if list == Path::new("-") {
+            io::stdin().read_to_string(&mut text)
+        } else {
+            File::open(list).and_then(|mut f| f.read_to_string(&mut text))
+        }
+        .map_err(|e| Failure::Input(format!("{}: {}", list.display(), e)))?;
+        inputs.extend(
+            text.lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(PathBuf::from),
+        );
+    }
+    if let Some(dir) = &batch.output_dir {
+        std::fs::create_dir_all(dir).map_err(Failure::Output)?;
+    }
+
+    let workers = batch.workers.clamp(1, inputs.len().max(1));
+    let contexts = (0..workers)
+        .map(|_| WhisperContext::new(&args.model.to_string_lossy()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let prompt_tokens = prompt_tokens(args, &contexts[0])?;
+    let pool = ContextPool::new(contexts);
+    let runner = BatchRunner {
+        formats: args.output_format.clone(),
+        output_dir: batch.output_dir.clone(),
+        force: batch.force,
+    };
+    let report = runner.run_with(
+        &pool,
+        &inputs,
+        |path| read_audio(path, args.pcm_format),
+        || full_params(args, &prompt_tokens),
+        |item| match &item.outcome {
+            BatchOutcome::Transcribed(timings) => log(
+                stderr,
+                format_args!(
+                    "{}: transcribed {:.1} s of audio in {:.1} s",
+                    item.input.display(),
+                    timings.audio.as_secs_f64(),
+                    timings.total.as_secs_f64()
+                ),
+            ),
+            BatchOutcome::UpToDate => {
+                log(stderr, format_args!("{}: up to date", item.input.display()))
+            }
+            BatchOutcome::Failed(e) => log(stderr, format_args!("{}: {}", item.input.display(), e)),
+        },
+    );
+    print!("{}", report);
+
+    match report.failed().count() {
+        0 => Ok(()),
+        n => Err(Failure::Batch(n)),
+    }
+}
+
+/// Reject combinations of arguments clap can't express.
+fn check_args(args: &Args) -> Result<(), clap::Error> {
+    use clap::error::ErrorKind;
+    let error = |kind, message| Err(Args::command().error(kind, message));
+    match &args.command {
+        None if args.input.is_none() => error(
+            ErrorKind::MissingRequiredArgument,
+            "the input is required unless running a subcommand",
+        ),
+        Some(Command::Batch(_)) if args.output.is_some() => error(
+            ErrorKind::ArgumentConflict,
+            "--output can't be used with batch, use --output-dir to choose where outputs go",
+        ),
+        _ => Ok(()),
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    if let Err(e) = check_args(&args) {
+        e.exit();
+    }
+
+    let result = {
+        // keep whisper.cpp's output out of ours, but attach it to errors
+        #[cfg(unix)]
+        let capture = whisper_rs::OutputCapture::install(whisper_rs::CaptureConfig {
+            stdout: false,
+            passthrough: args.verbose,
+            ..Default::default()
+        })
+        .ok();
+        #[cfg(unix)]
+        let stderr: Box<dyn Write + Send> = match capture.as_ref().map(|c| c.original_stderr()) {
+            Some(Ok(stderr)) => Box::new(stderr),
+            _ => Box::new(io::stderr()),
+        };
+        #[cfg(not(unix))]
+        let stderr: Box<dyn Write + Send> = Box::new(io::stderr());
+        let stderr = Mutex::new(stderr);
+        match &args.command {
+            Some(Command::Batch(batch)) => run_batch(&args, batch, &stderr),
+            None => run(
+                &args,
+                args.input.as_deref().unwrap_or(Path::new("-")),
+                &stderr,
+            ),
+        }
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => {
+            eprintln!("error: {}", failure);
+            ExitCode::from(failure.exit_code())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_arguments() {
+        Args::command().debug_assert();
+
+        let args = Args::try_parse_from(["whisper-rs", "-m", "model.bin", "audio.wav"]).unwrap();
+        assert!(args.command.is_none());
+        assert!(Args::try_parse_from(["whisper-rs", "audio.wav"]).is_err());
+
+        let args = Args::try_parse_from([
+            "whisper-rs",
+            "-m",
+            "model.bin",
+            "-f",
+            "srt,json",
+            "batch",
+            "--workers",
+            "2",
+            "a.wav",
+            "b.wav",
+        ])
+        .unwrap();
+        let Some(Command::Batch(batch)) = args.command else {
+            panic!("expected the batch subcommand");
+        };
+        assert_eq!(batch.workers, 2);
+        assert_eq!(
+            batch.inputs,
+            [PathBuf::from("a.wav"), PathBuf::from("b.wav")]
+        );
+        assert_eq!(args.output_format, [OutputFormat::Srt, OutputFormat::Json]);
+        assert!(Args::try_parse_from(["whisper-rs", "batch", "a.wav"]).is_err());
+
+        let args = Args::try_parse_from([
+            "whisper-rs",
+            "-m",
+            "model.bin",
+            "-o",
+            "out",
+            "batch",
+            "a.wav",
+        ])
+        .unwrap();
+        assert_eq!(
+            check_args(&args).unwrap_err().kind(),
+            clap::error::ErrorKind::ArgumentConflict
+        );
+    }
+}
@@ -0,0 +1,723 @@
+//! Capture of the diagnostic output whisper.cpp prints to stdout and stderr.
+//!
+//! whisper.cpp has no logging hook, so [OutputCapture] redirects the process-wide file descriptors
+//! into a pipe and reads them back on a background thread.
+
+use std::fmt;
+
+/// Severity of a [CapturedLine], guessed from its text.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CaptureLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl CaptureLevel {
+    /// Guess the severity of a line printed by whisper.cpp.
+    ///
+    /// Lines mentioning an error or failure are errors, lines mentioning a warning are warnings,
+    /// timing reports and system info are debug output, and anything else is info.
+    pub fn parse(line: &str) -> Self {
+        let lower = line.to_ascii_lowercase();
+        if lower.contains("error") || lower.contains("failed") || lower.contains("invalid") {
+            Self::Error
+        } else if lower.contains("warning") || lower.contains("warn:") {
+            Self::Warn
+        } else if lower.starts_with("whisper_print_timings")
+            || lower.starts_with("system_info")
+            || lower.contains(" time = ")
+        {
+            Self::Debug
+        } else {
+            Self::Info
+        }
+    }
+}
+
+/// A single line of whisper.cpp output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedLine {
+    /// Guessed severity of the line.
+    pub level: CaptureLevel,
+    /// Text of the line, without the trailing newline. Invalid UTF-8 is replaced with U+FFFD.
+    pub text: String,
+}
+
+impl CapturedLine {
+    fn new(text: String) -> Self {
+        Self {
+            level: CaptureLevel::parse(&text),
+            text,
+        }
+    }
+}
+
+impl fmt::Display for CapturedLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Where [OutputCapture] forwards captured lines.
+pub enum CaptureSink {
+    /// Only keep lines in the buffer, to be attached to errors.
+    Discard,
+    /// Emit `log` records with the target `whisper_cpp`.
+    #[cfg(feature = "log")]
+    Log,
+    /// Emit `tracing` events with the target `whisper_cpp`.
+    #[cfg(feature = "tracing")]
+    Tracing,
+    /// Call a function for every line. Runs on the capture thread.
+    Callback(Box<dyn Fn(&CapturedLine) + Send + Sync>),
+}
+
+impl fmt::Debug for CaptureSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Discard => f.write_str("Discard"),
+            #[cfg(feature = "log")]
+            Self::Log => f.write_str("Log"),
+            #[cfg(feature = "tracing")]
+            Self::Tracing => f.write_str("Tracing"),
+            Self::Callback(_) => f.write_str("Callback(..)"),
+        }
+    }
+}
+
+impl CaptureSink {
+    fn emit(&self, line: &CapturedLine) {
+        match self {
+            Self::Discard => {}
+            #[cfg(feature = "log")]
+            Self::Log => {
+                let level = match line.level {
+                    CaptureLevel::Error => log::Level::Error,
+                    CaptureLevel::Warn => log::Level::Warn,
+                    CaptureLevel::Info => log::Level::Info,
+                    CaptureLevel::Debug => log::Level::Debug,
+                };
+                log::log!(target: "whisper_cpp", level, "{}", line.text);
+            }
+            #[cfg(feature = "tracing")]
+            Self::Tracing => match line.level {
+                CaptureLevel::Error => tracing::error!(target: "whisper_cpp", "{}", line.text),
+                CaptureLevel::Warn => tracing::warn!(target: "whisper_cpp", "{}", line.text),
+                CaptureLevel::Info => tracing::info!(target: "whisper_cpp", "{}", line.text),
+                CaptureLevel::Debug => tracing::debug!(target: "whisper_cpp", "{}", line.text),
+            },
+            Self::Callback(callback) => callback(line),
+        }
+    }
+}
+
+/// Configuration for [OutputCapture::install].
+#[derive(Debug)]
+pub struct CaptureConfig {
+    /// Capture stdout. whisper.cpp prints progress and results there if the matching
+    /// `FullParams::set_print_*` options are enabled.
+    ///
+    /// Defaults to true.
+    pub stdout: bool,
+    /// Capture stderr. whisper.cpp prints model loading info, timings and errors there.
+    ///
+    /// Defaults to true.
+    pub stderr: bool,
+    /// Where to forward captured lines.
+    ///
+    /// Defaults to [CaptureSink::Discard].
+    pub sink: CaptureSink,
+    /// Also write every captured line to the original stderr.
+    ///
+    /// Defaults to false.
+    pub passthrough: bool,
+    /// Number of recent lines kept for attaching to errors.
+    ///
+    /// Defaults to 256.
+    pub buffer_lines: usize,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            stdout: true,
+            stderr: true,
+            sink: CaptureSink::Discard,
+            passthrough: false,
+            buffer_lines: 256,
+        }
+    }
+}
+
+#[cfg(unix)]
+pub use imp::OutputCapture;
+#[cfg(unix)]
+pub(crate) use imp::{checkpoint, lines_since, quiet};
+
+/// Capturing is only supported on Unix, so there is never anything to attach.
+#[cfg(not(unix))]
+pub(crate) fn checkpoint() -> Option<u64> {
+    None
+}
+
+#[cfg(not(unix))]
+pub(crate) fn lines_since(_checkpoint: Option<u64>) -> Vec<CapturedLine> {
+    Vec::new()
+}
+
+/// Run `f`, collecting what whisper.cpp prints meanwhile.
+///
+/// Uses the installed [OutputCapture] if there is one, otherwise installs one capturing stderr until `f` returns.
+#[cfg(unix)]
+pub(crate) fn collect<T>(f: impl FnOnce() -> T) -> (T, Vec<CapturedLine>) {
+    if let Some(checkpoint) = checkpoint() {
+        let ret = f();
+        return (ret, lines_since(Some(checkpoint)));
+    }
+    match OutputCapture::install(CaptureConfig {
+        stdout: false,
+        ..Default::default()
+    }) {
+        Ok(capture) => {
+            let ret = f();
+            (ret, capture.recent())
+        }
+        // someone else installed a capture in the meantime
+        Err(_) => {
+            let checkpoint = checkpoint();
+            let ret = f();
+            (ret, lines_since(checkpoint))
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn collect<T>(f: impl FnOnce() -> T) -> (T, Vec<CapturedLine>) {
+    (f(), Vec::new())
+}
+
+/// Without a way to capture stderr, `f` would print, so it isn't run.
+#[cfg(not(unix))]
+pub(crate) fn quiet(_prefix: &str, _f: impl FnOnce()) -> Option<Vec<CapturedLine>> {
+    None
+}
+
+#[cfg(unix)]
+mod imp {
+    use super::{CaptureConfig, CaptureSink, CapturedLine};
+    use std::collections::VecDeque;
+    use std::fs::File;
+    use std::io::{self, BufRead, BufReader, Write};
+    use std::os::unix::io::{FromRawFd, RawFd};
+    use std::sync::{Arc, Condvar, Mutex};
+    use std::thread::JoinHandle;
+    use std::time::Duration;
+
+    /// Written into the pipe to find out when the reader thread has caught up.
+    const SYNC_MARKER: &str = "\u{1}whisper-rs-sync ";
+    /// Written into the pipe around output only whisper-rs reads, followed by the prefix of the lines to hide,
+    /// or nothing at the end.
+    const QUIET_MARKER: &str = "\u{1}whisper-rs-quiet ";
+    /// How long to wait for the reader thread to catch up before giving up.
+    const SYNC_TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// The currently installed capture, if any.
+    static ACTIVE: Mutex<Option<Arc<Shared>>> = Mutex::new(None);
+
+    struct Shared {
+        buffer: Mutex<Buffer>,
+        synced: Condvar,
+        /// Write end of the pipe, used for sync markers.
+        write_fd: RawFd,
+        /// (captured fd, saved copy of the original), emptied once the originals are restored.
+        saved: Mutex<Vec<(RawFd, RawFd)>>,
+    }
+
+    struct Buffer {
+        lines: VecDeque<(u64, CapturedLine)>,
+        capacity: usize,
+        /// Sequence number of the next captured line.
+        next_seq: u64,
+        /// Number of sync markers sent.
+        sent: u64,
+        /// Number of sync markers the reader thread has seen.
+        received: u64,
+        /// Lines hidden by [quiet], not yet taken.
+        quiet: Vec<CapturedLine>,
+    }
+
+    /// A marker written into the pipe by whisper-rs itself.
+    enum Marker {
+        Sync(u64),
+        /// Start hiding lines with this prefix, or stop if it's empty.
+        Quiet(String),
+    }
+
+    impl Shared {
+        /// Flush C stdio buffers and wait until the reader thread has read everything written so far.
+        ///
+        /// # Returns
+        /// The sequence number of the next line to be captured.
+        fn sync(&self) -> u64 {
+            // stdout is fully buffered when it's a pipe, so flush it first
+            unsafe { libc::fflush(std::ptr::null_mut()) };
+            let target = {
+                let mut buffer = self.buffer.lock().unwrap_or_else(|e| e.into_inner());
+                buffer.sent += 1;
+                buffer.sent
+            };
+            // don't hold the lock while writing, the reader may need it to drain a full pipe
+            let marker = format!("{}{}\n", SYNC_MARKER, target);
+            // short writes to a pipe are atomic
+            let written =
+                unsafe { libc::write(self.write_fd, marker.as_ptr().cast(), marker.len()) };
+            let buffer = self.buffer.lock().unwrap_or_else(|e| e.into_inner());
+            let buffer = if written == marker.len() as isize {
+                self.synced
+                    .wait_timeout_while(buffer, SYNC_TIMEOUT, |b| b.received < target)
+                    .unwrap_or_else(|e| e.into_inner())
+                    .0
+            } else {
+                buffer
+            };
+            buffer.next_seq
+        }
+
+        /// Write a marker into the pipe, behind everything written so far.
+        fn mark(&self, marker: &str) {
+            unsafe { libc::fflush(std::ptr::null_mut()) };
+            let marker = format!("{}\n", marker);
+            unsafe { libc::write(self.write_fd, marker.as_ptr().cast(), marker.len()) };
+        }
+    }
+
+    /// Redirects stdout and/or stderr of the whole process into a pipe, and forwards every line
+    /// to a [CaptureSink](super::CaptureSink).
+    ///
+    /// While installed, recent lines are also attached to errors returned by
+    /// [WhisperContext](crate::WhisperContext) methods,
+    /// see [WhisperError::output](crate::WhisperError::output).
+    ///
+    /// The redirection is process-wide: output of other threads and libraries, including Rust's own
+    /// `eprintln!`, is captured as well, and lines of calls running in parallel may be attributed to
+    /// each other's errors.
+    ///
+    /// While the sink runs, stdout and stderr point back at the original file descriptors,
+    /// so a logger writing to them doesn't feed its own output back into the capture.
+    /// Output of other threads during that time isn't captured either.
+    ///
+    /// The original file descriptors are restored when this is dropped.
+    /// Only one capture can be installed at a time.
+    pub struct OutputCapture {
+        shared: Arc<Shared>,
+        original_stderr: RawFd,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl OutputCapture {
+        /// Start capturing.
+        ///
+        /// # Arguments
+        /// * config: What to capture and where to send it.
+        ///
+        /// # Returns
+        /// Ok(Self) on success. Err if a capture is already installed, or a file descriptor
+        /// couldn't be redirected.
+        pub fn install(config: CaptureConfig) -> io::Result<Self> {
+            let mut active = ACTIVE.lock().unwrap_or_else(|e| e.into_inner());
+            if active.is_some() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    "an output capture is already installed",
+                ));
+            }
+
+            let mut fds = [0; 2];
+            cvt(unsafe { libc::pipe(fds.as_mut_ptr()) })?;
+            let [read_fd, write_fd] = fds;
+            let original_stderr = match cvt(unsafe { libc::dup(libc::STDERR_FILENO) }) {
+                Ok(fd) => fd,
+                Err(e) => {
+                    close_all(&[read_fd, write_fd]);
+                    return Err(e);
+                }
+            };
+
+            let targets = [
+                (config.stdout, libc::STDOUT_FILENO),
+                (config.stderr, libc::STDERR_FILENO),
+            ];
+            unsafe { libc::fflush(std::ptr::null_mut()) };
+            let mut saved = Vec::new();
+            for fd in targets.iter().filter(|(on, _)| *on).map(|(_, fd)| *fd) {
+                let result = cvt(unsafe { libc::dup(fd) }).and_then(|copy| {
+                    cvt(unsafe { libc::dup2(write_fd, fd) })
+                        .map(|_| copy)
+                        .inspect_err(|_| close_all(&[copy]))
+                });
+                match result {
+                    Ok(copy) => saved.push((fd, copy)),
+                    Err(e) => {
+                        restore(&mut saved);
+                        close_all(&[read_fd, write_fd, original_stderr]);
+                        return Err(e);
+                    }
+                }
+            }
+
+            let shared = Arc::new(Shared {
+                buffer: Mutex::new(Buffer {
+                    lines: VecDeque::new(),
+                    capacity: config.buffer_lines,
+                    next_seq: 0,
+                    sent: 0,
+                    received: 0,
+                    quiet: Vec::new(),
+                }),
+                synced: Condvar::new(),
+                write_fd,
+                saved: Mutex::new(saved),
+            });
+            let passthrough = if config.passthrough {
+                cvt(unsafe { libc::dup(original_stderr) })
+                    .ok()
+                    .map(|fd| unsafe { File::from_raw_fd(fd) })
+            } else {
+                None
+            };
+            let reader = unsafe { File::from_raw_fd(read_fd) };
+            let thread_shared = Arc::clone(&shared);
+            let thread = std::thread::Builder::new()
+                .name("whisper-rs-capture".to_string())
+                .spawn(move || read_lines(reader, thread_shared, config.sink, passthrough));
+            let thread = match thread {
+                Ok(thread) => thread,
+                Err(e) => {
+                    restore(&mut shared.saved.lock().unwrap_or_else(|e| e.into_inner()));
+                    close_all(&[write_fd, original_stderr]);
+                    return Err(e);
+                }
+            };
+
+            *active = Some(Arc::clone(&shared));
+            Ok(Self {
+                shared,
+                original_stderr,
+                thread: Some(thread),
+            })
+        }
+
+        /// A handle to the stderr of the process from before the capture was installed.
+        pub fn original_stderr(&self) -> io::Result<File> {
+            let fd = cvt(unsafe { libc::dup(self.original_stderr) })?;
+            Ok(unsafe { File::from_raw_fd(fd) })
+        }
+
+        /// The most recently captured lines, oldest first.
+        pub fn recent(&self) -> Vec<CapturedLine> {
+            self.shared.sync();
+            let buffer = self.shared.buffer.lock().unwrap_or_else(|e| e.into_inner());
+            buffer.lines.iter().map(|(_, line)| line.clone()).collect()
+        }
+    }
+
+    impl Drop for OutputCapture {
+        fn drop(&mut self) {
+            // release the lock before waiting for the reader thread, whose sink may call back into whisper-rs
+            ACTIVE.lock().unwrap_or_else(|e| e.into_inner()).take();
+            self.shared.sync();
+            restore(&mut self.shared.saved.lock().unwrap_or_else(|e| e.into_inner()));
+            // the reader thread sees EOF once every write end is closed
+            close_all(&[self.shared.write_fd, self.original_stderr]);
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+
+    impl std::fmt::Debug for OutputCapture {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("OutputCapture")
+                .field(
+                    "saved",
+                    &*self.shared.saved.lock().unwrap_or_else(|e| e.into_inner()),
+                )
+                .finish_non_exhaustive()
+        }
+    }
+
+    fn read_lines(
+        reader: File,
+        shared: Arc<Shared>,
+        sink: CaptureSink,
+        mut passthrough: Option<File>,
+    ) {
+        let mut reader = BufReader::new(reader);
+        let mut raw = Vec::new();
+        let mut quiet_prefix: Option<String> = None;
+        loop {
+            raw.clear();
+            match reader.read_until(b'\n', &mut raw) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+            let text = String::from_utf8_lossy(&raw);
+            let text = text.trim_end_matches(['\n', '\r']);
+            let (text, marker) = split_marker(text);
+
+            let quiet = quiet_prefix
+                .as_deref()
+                .is_some_and(|prefix| text.starts_with(prefix));
+            if quiet {
+                let mut buffer = shared.buffer.lock().unwrap_or_else(|e| e.into_inner());
+                buffer.quiet.push(CapturedLine::new(text.to_string()));
+            } else if !text.trim().is_empty() {
+                let line = CapturedLine::new(text.to_string());
+                if let Some(out) = passthrough.as_mut() {
+                    let _ = writeln!(out, "{}", line.text);
+                }
+                emit(&sink, &line, &shared);
+                let mut buffer = shared.buffer.lock().unwrap_or_else(|e| e.into_inner());
+                let seq = buffer.next_seq;
+                buffer.next_seq += 1;
+                buffer.lines.push_back((seq, line));
+                while buffer.lines.len() > buffer.capacity {
+                    buffer.lines.pop_front();
+                }
+            }
+            match marker {
+                Some(Marker::Sync(n)) => {
+                    let mut buffer = shared.buffer.lock().unwrap_or_else(|e| e.into_inner());
+                    buffer.received = buffer.received.max(n);
+                    shared.synced.notify_all();
+                }
+                Some(Marker::Quiet(prefix)) => {
+                    quiet_prefix = Some(prefix).filter(|prefix| !prefix.is_empty());
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Split a line into the output before a marker, and the marker.
+    fn split_marker(text: &str) -> (&str, Option<Marker>) {
+        // a marker may follow output that didn't end with a newline
+        if let Some(i) = text.find(SYNC_MARKER) {
+            let n = text[i + SYNC_MARKER.len()..].parse().ok();
+            (&text[..i], n.map(Marker::Sync))
+        } else if let Some(i) = text.find(QUIET_MARKER) {
+            let prefix = text[i + QUIET_MARKER.len()..].to_string();
+            (&text[..i], Some(Marker::Quiet(prefix)))
+        } else {
+            (text, None)
+        }
+    }
+
+    /// Run `f`, reading back the lines starting with `prefix` it prints to stderr, without passing them to the sink,
+    /// the original stderr or the buffered lines.
+    ///
+    /// Uses the installed [OutputCapture] if there is one, otherwise installs one capturing stderr until `f` returns,
+    /// passing other lines through to the original stderr.
+    ///
+    /// # Returns
+    /// None without running `f` if stderr can't be captured.
+    pub(crate) fn quiet(prefix: &str, f: impl FnOnce()) -> Option<Vec<CapturedLine>> {
+        // calls at the same time must not read each other's lines
+        static QUIET: Mutex<()> = Mutex::new(());
+        let _guard = QUIET.lock().unwrap_or_else(|e| e.into_inner());
+        let active = ACTIVE.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        let (shared, _capture) = match active {
+            Some(shared) => (shared, None),
+            None => {
+                let capture = OutputCapture::install(CaptureConfig {
+                    stdout: false,
+                    passthrough: true,
+                    ..Default::default()
+                })
+                .ok()?;
+                (Arc::clone(&capture.shared), Some(capture))
+            }
+        };
+        let captures_stderr = shared
+            .saved
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .any(|&(fd, _)| fd == libc::STDERR_FILENO);
+        if !captures_stderr {
+            return None;
+        }
+        shared.mark(&format!("{}{}", QUIET_MARKER, prefix));
+        f();
+        shared.mark(QUIET_MARKER);
+        shared.sync();
+        let mut buffer = shared.buffer.lock().unwrap_or_else(|e| e.into_inner());
+        Some(std::mem::take(&mut buffer.quiet))
+    }
+
+    /// Forward a line to the sink, with stdout and stderr pointing at the originals meanwhile.
+    fn emit(sink: &CaptureSink, line: &CapturedLine, shared: &Shared) {
+        if let CaptureSink::Discard = sink {
+            return;
+        }
+        let saved = shared.saved.lock().unwrap_or_else(|e| e.into_inner());
+        for &(fd, copy) in saved.iter() {
+            unsafe { libc::dup2(copy, fd) };
+        }
+        sink.emit(line);
+        let _ = io::stdout().flush();
+        for &(fd, _) in saved.iter() {
+            unsafe { libc::dup2(shared.write_fd, fd) };
+        }
+    }
+
+    /// Mark the current position in the captured output.
+    ///
+    /// This is called before every call into whisper.cpp, so it doesn't wait for the reader thread:
+    /// output printed shortly before may still be attributed to the call.
+    ///
+    /// # Returns
+    /// None if no capture is installed.
+    pub(crate) fn checkpoint() -> Option<u64> {
+        let shared = ACTIVE.lock().unwrap_or_else(|e| e.into_inner()).clone()?;
+        let buffer = shared.buffer.lock().unwrap_or_else(|e| e.into_inner());
+        Some(buffer.next_seq)
+    }
+
+    /// Lines captured since a [checkpoint], if they are still buffered.
+    pub(crate) fn lines_since(checkpoint: Option<u64>) -> Vec<CapturedLine> {
+        let (Some(checkpoint), Some(shared)) = (
+            checkpoint,
+            ACTIVE.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+        ) else {
+            return Vec::new();
+        };
+        shared.sync();
+        let buffer = shared.buffer.lock().unwrap_or_else(|e| e.into_inner());
+        buffer
+            .lines
+            .iter()
+            .filter(|(seq, _)| *seq >= checkpoint)
+            .map(|(_, line)| line.clone())
+            .collect()
+    }
+
+    fn cvt(ret: libc::c_int) -> io::Result<libc::c_int> {
+        if ret < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(ret)
+        }
+    }
+
+    fn restore(saved: &mut Vec<(RawFd, RawFd)>) {
+        unsafe { libc::fflush(std::ptr::null_mut()) };
+        for (fd, copy) in saved.drain(..) {
+            unsafe {
+                libc::dup2(copy, fd);
+                libc::close(copy);
+            }
+        }
+    }
+
+    fn close_all(fds: &[RawFd]) {
+        for &fd in fds {
+            unsafe { libc::close(fd) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_levels() {
+        let level = CaptureLevel::parse;
+        assert_eq!(
+            level("whisper_model_load: failed to open 'model.bin'"),
+            CaptureLevel::Error
+        );
+        assert_eq!(
+            level("whisper_model_load: WARNING: unknown ftype"),
+            CaptureLevel::Warn
+        );
+        assert_eq!(
+            level("whisper_print_timings:     load time =   100.00 ms"),
+            CaptureLevel::Debug
+        );
+        assert_eq!(
+            level("whisper_model_load: n_vocab = 51865"),
+            CaptureLevel::Info
+        );
+    }
+
+    /// Redirecting stderr affects every test running in parallel, so this runs alone in a child process.
+    #[cfg(unix)]
+    #[test]
+    fn capture_and_attach() {
+        if std::env::var_os("WHISPER_RS_CAPTURE_TEST").is_none() {
+            let output = std::process::Command::new(std::env::current_exe().unwrap())
+                .args(["--exact", "capture::test::capture_and_attach"])
+                .env("WHISPER_RS_CAPTURE_TEST", "1")
+                .output()
+                .expect("run the test in a child process");
+            assert!(
+                output.status.success(),
+                "{}",
+                String::from_utf8_lossy(&output.stdout)
+            );
+            return;
+        }
+
+        static FORWARDED: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let capture = OutputCapture::install(CaptureConfig {
+            stdout: false,
+            sink: CaptureSink::Callback(Box::new(|line| {
+                FORWARDED.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                // what the sink prints must not be captured again
+                use std::io::Write;
+                let _ = writeln!(std::io::stderr(), "forwarded: {}", line);
+                // a sink calling into whisper-rs must not deadlock when the capture is dropped meanwhile
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                checkpoint();
+            })),
+            ..Default::default()
+        })
+        .expect("install capture");
+        assert!(OutputCapture::install(CaptureConfig::default()).is_err());
+
+        let before = checkpoint();
+        assert!(before.is_some());
+        let message = b"whisper_init: failed to load model\n";
+        unsafe { libc::write(libc::STDERR_FILENO, message.as_ptr().cast(), message.len()) };
+        let lines = lines_since(before);
+        assert_eq!(
+            lines,
+            vec![CapturedLine {
+                level: CaptureLevel::Error,
+                text: "whisper_init: failed to load model".to_string(),
+            }]
+        );
+        assert_eq!(capture.recent(), lines);
+
+        // lines read back quietly only reach the caller
+        let print_timing = || {
+            let timing = b"whisper_print_timings:   encode time =    12.00 ms\n";
+            unsafe { libc::write(libc::STDERR_FILENO, timing.as_ptr().cast(), timing.len()) };
+        };
+        let quiet_lines = quiet("whisper_print_timings", print_timing).unwrap();
+        assert_eq!(quiet_lines.len(), 1);
+        assert_eq!(capture.recent(), lines);
+        assert_eq!(FORWARDED.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        unsafe { libc::write(libc::STDERR_FILENO, message.as_ptr().cast(), message.len()) };
+        drop(capture);
+        assert_eq!(checkpoint(), None);
+        let quiet_lines = quiet("whisper_print_timings", print_timing).unwrap();
+        assert_eq!(quiet_lines.len(), 1);
+        assert_eq!(checkpoint(), None);
+    }
+}
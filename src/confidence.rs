@@ -0,0 +1,155 @@
+//! Per-segment confidence statistics.
+
+use crate::segment::Segment;
+
+/// Confidence statistics of a [Segment], computed over its text tokens.
+///
+/// Obtained with [Segment::confidence].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SegmentConfidence {
+    /// Number of text tokens the statistics were computed over.
+    pub n_tokens: usize,
+    /// Average log probability of the tokens.
+    pub avg_logprob: f32,
+    /// Lowest probability of any token.
+    pub min_probability: f32,
+    /// Share of tokens with a probability below the threshold passed to [Segment::confidence], from 0 to 1.
+    pub low_probability_fraction: f32,
+    /// Average probability of the most likely timestamp token at each token (`pt`).
+    pub avg_timestamp_probability: f32,
+    /// Average summed probability of all timestamp tokens at each token (`ptsum`).
+    pub avg_timestamp_probability_sum: f32,
+}
+
+/// Decides which segments should be routed to human review.
+///
+/// A segment needs review if any of the limits is exceeded.
+/// Segments without any text tokens never need review.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ReviewPolicy {
+    /// Tokens with a probability below this count as low probability.
+    ///
+    /// Defaults to 0.5.
+    pub low_probability_threshold: f32,
+    /// Review segments whose average log probability is below this.
+    ///
+    /// Defaults to -1.0, the same as [FullParams::set_logprob_thold](crate::FullParams::set_logprob_thold).
+    pub min_avg_logprob: f32,
+    /// Review segments containing a token with a probability below this.
+    ///
+    /// Defaults to 0.1.
+    pub min_probability: f32,
+    /// Review segments where more than this share of tokens are low probability.
+    ///
+    /// Defaults to 0.25.
+    pub max_low_probability_fraction: f32,
+}
+
+impl Default for ReviewPolicy {
+    fn default() -> Self {
+        Self {
+            low_probability_threshold: 0.5,
+            min_avg_logprob: -1.0,
+            min_probability: 0.1,
+            max_low_probability_fraction: 0.25,
+        }
+    }
+}
+
+impl ReviewPolicy {
+    /// Does the segment need human review under this policy?
+    pub fn needs_review(&self, segment: &Segment) -> bool {
+        let confidence = segment.confidence(self.low_probability_threshold);
+        confidence.n_tokens > 0
+            && (confidence.avg_logprob < self.min_avg_logprob
+                || confidence.min_probability < self.min_probability
+                || confidence.low_probability_fraction > self.max_low_probability_fraction)
+    }
+}
+
+impl Segment {
+    /// Compute confidence statistics over the text tokens of this segment.
+    ///
+    /// # Arguments
+    /// * low_probability_threshold: Tokens with a probability below this count towards
+    ///   [SegmentConfidence::low_probability_fraction].
+    ///
+    /// # Returns
+    /// [SegmentConfidence]. If the segment has no text tokens, averages are 0 and the minimum is 1.
+    pub fn confidence(&self, low_probability_threshold: f32) -> SegmentConfidence {
+        let mut confidence = SegmentConfidence {
+            n_tokens: 0,
+            avg_logprob: 0.0,
+            min_probability: 1.0,
+            low_probability_fraction: 0.0,
+            avg_timestamp_probability: 0.0,
+            avg_timestamp_probability_sum: 0.0,
+        };
+        for token in self.tokens.iter().filter(|t| t.kind.is_text()) {
+            let data = &token.data;
+            confidence.n_tokens += 1;
+            confidence.avg_logprob += data.plog;
+            confidence.min_probability = confidence.min_probability.min(data.p);
+            if data.p < low_probability_threshold {
+                confidence.low_probability_fraction += 1.0;
+            }
+            confidence.avg_timestamp_probability += data.pt;
+            confidence.avg_timestamp_probability_sum += data.ptsum;
+        }
+        if confidence.n_tokens > 0 {
+            let n = confidence.n_tokens as f32;
+            confidence.avg_logprob /= n;
+            confidence.low_probability_fraction /= n;
+            confidence.avg_timestamp_probability /= n;
+            confidence.avg_timestamp_probability_sum /= n;
+        }
+        confidence
+    }
+
+    /// Does this segment need human review? See [ReviewPolicy].
+    #[inline]
+    pub fn needs_review(&self, policy: &ReviewPolicy) -> bool {
+        policy.needs_review(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{SegmentToken, Timestamp, TokenKind};
+
+    fn segment(probs: &[f32]) -> Segment {
+        let tokens = probs
+            .iter()
+            .map(|&p| {
+                let mut token = SegmentToken::for_test(0, TokenKind::Text, b" a", p, -1, -1);
+                token.data.pt = 0.5;
+                token.data.ptsum = 1.0;
+                token
+            })
+            .collect();
+        Segment {
+            t0: Timestamp::ZERO,
+            t1: Timestamp::from_centis(100),
+            text: String::new(),
+            tokens,
+        }
+    }
+
+    #[test]
+    fn confidence_and_review() {
+        let confident = segment(&[0.9, 0.8, 0.95, 0.9]);
+        let confidence = confident.confidence(0.5);
+        assert_eq!(confidence.n_tokens, 4);
+        assert_eq!(confidence.min_probability, 0.8);
+        assert_eq!(confidence.low_probability_fraction, 0.0);
+        assert_eq!(confidence.avg_timestamp_probability, 0.5);
+        assert!(!confident.needs_review(&ReviewPolicy::default()));
+
+        let uncertain = segment(&[0.9, 0.3, 0.4, 0.9]);
+        assert_eq!(uncertain.confidence(0.5).low_probability_fraction, 0.5);
+        assert!(uncertain.needs_review(&ReviewPolicy::default()));
+
+        assert!(!segment(&[]).needs_review(&ReviewPolicy::default()));
+    }
+}
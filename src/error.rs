@@ -1,11 +1,22 @@
+use crate::capture::{self, CaptureLevel, CapturedLine};
+use crate::segment::Segment;
+use crate::timestamp::Timestamp;
 use std::ffi::{c_int, NulError};
+use std::fmt;
 use std::str::Utf8Error;
 
-/// Whisper tends to output errors to stderr, so if an error occurs, check stderr.
-#[derive(Debug, Copy, Clone)]
+/// Whisper tends to output errors to stderr, so if an error occurs, check stderr,
+/// or install an [OutputCapture](crate::OutputCapture) to have it attached to the error.
+///
+/// Errors returned by [WhisperContext](crate::WhisperContext) methods that call into whisper.cpp
+/// are wrapped in [WhisperError::Operation], recording the call and its parameters.
+/// Use [WhisperError::root_cause] to get the underlying error.
+#[derive(Debug, Clone, PartialEq)]
 pub enum WhisperError {
     /// Failed to create a new context.
     InitError,
+    /// Failed to open or memory-map the model file, with the message of the I/O error.
+    FailedToMapModel(String),
     /// User didn't initialize spectrogram
     SpectrogramNotInitialized,
     /// Encode was not called.
@@ -20,10 +31,20 @@ pub enum WhisperError {
     FailedToEncode,
     /// Failed to run the decoder
     FailedToDecode,
+    /// Failed to auto-detect the spoken language.
+    FailedToDetectLanguage,
+    /// Failed to set up the decoders requested by the sampling strategy.
+    FailedToInitializeDecoders,
+    /// The audio context set with [FullParams::set_audio_ctx](crate::FullParams::set_audio_ctx)
+    /// is larger than the model supports.
+    AudioCtxTooLarge,
     /// Invalid number of mel bands.
     InvalidMelBands,
     /// Invalid thread count
     InvalidThreadCount,
+    /// [WhisperContext::decode](crate::WhisperContext::decode) was asked to continue from more tokens
+    /// than were decoded since the last encode.
+    NPastTooLarge { n_past: usize, n_known: usize },
     /// Invalid UTF-8 detected in a string from Whisper.
     InvalidUtf8 {
         error_len: Option<usize>,
@@ -37,6 +58,252 @@ pub enum WhisperError {
     GenericError(c_int),
     /// Whisper failed to convert the provided text into tokens.
     InvalidText,
+    /// The transcription was stopped by the flag set with [FullParams::set_abort_flag](crate::FullParams::set_abort_flag).
+    /// Segments decoded before that are still available from the context.
+    Aborted,
+    /// The deadline of [WhisperContext::full_with_timeout](crate::WhisperContext::full_with_timeout) passed.
+    Timeout {
+        /// Segments decoded before the deadline.
+        segments: Vec<Segment>,
+        /// Position in the audio up to which it was transcribed, to retry the remainder from.
+        position: Timestamp,
+    },
+    /// An operation failed.
+    Operation {
+        /// The operation that failed, and its parameters.
+        operation: Operation,
+        /// Why it failed.
+        error: Box<WhisperError>,
+        /// Output whisper.cpp printed during the call.
+        /// Always empty unless an [OutputCapture](crate::OutputCapture) is installed.
+        output: Vec<CapturedLine>,
+    },
+}
+
+/// A call into whisper.cpp, with the parameters it was made with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    /// [WhisperContext::new](crate::WhisperContext::new)
+    Init { path: String },
+    /// [WhisperContext::new_from_buffer](crate::WhisperContext::new_from_buffer)
+    InitFromBuffer { n_bytes: usize },
+    /// [WhisperContext::new_from_mmap](crate::WhisperContext::new_from_mmap)
+    InitFromMmap { path: String },
+    /// [WhisperContext::pcm_to_mel](crate::WhisperContext::pcm_to_mel)
+    PcmToMel { n_samples: usize, threads: usize },
+    /// [WhisperContext::set_mel](crate::WhisperContext::set_mel)
+    SetMel { n_len: usize },
+    /// [WhisperContext::encode](crate::WhisperContext::encode)
+    Encode { offset: usize, threads: usize },
+    /// [WhisperContext::decode](crate::WhisperContext::decode)
+    Decode {
+        n_tokens: usize,
+        n_past: usize,
+        threads: usize,
+    },
+    /// [WhisperContext::tokenize](crate::WhisperContext::tokenize)
+    Tokenize { text_len: usize },
+    /// [WhisperContext::lang_detect](crate::WhisperContext::lang_detect)
+    LangDetect { offset_ms: usize, threads: usize },
+    /// [WhisperContext::full](crate::WhisperContext::full)
+    Full { n_samples: usize, threads: c_int },
+    /// [WhisperContext::full_parallel](crate::WhisperContext::full_parallel)
+    FullParallel {
+        n_samples: usize,
+        threads: c_int,
+        n_processors: c_int,
+    },
+    /// A worker of [ContextPool::transcribe_parallel](crate::ContextPool::transcribe_parallel)
+    TranscribeParallel {
+        worker: usize,
+        start: Timestamp,
+        end: Timestamp,
+    },
+}
+
+impl WhisperError {
+    /// Record the operation this error occurred in, and the output printed since `checkpoint`.
+    pub(crate) fn with_operation(self, operation: Operation, checkpoint: Option<u64>) -> Self {
+        Self::Operation {
+            operation,
+            error: Box::new(self),
+            output: capture::lines_since(checkpoint),
+        }
+    }
+
+    /// Map a return code of `whisper_full` or `whisper_full_parallel` to an error.
+    pub(crate) fn from_full_code(code: c_int) -> Self {
+        match code {
+            -1 | -2 => Self::UnableToCalculateSpectrogram,
+            -3 => Self::FailedToDetectLanguage,
+            -4 => Self::FailedToInitializeDecoders,
+            -5 => Self::AudioCtxTooLarge,
+            -6 => Self::FailedToEncode,
+            -7 | -8 => Self::FailedToDecode,
+            code => Self::GenericError(code),
+        }
+    }
+
+    /// The underlying error, with any [WhisperError::Operation] context removed.
+    pub fn root_cause(&self) -> &WhisperError {
+        match self {
+            Self::Operation { error, .. } => error.root_cause(),
+            error => error,
+        }
+    }
+
+    /// Output whisper.cpp printed during the failed call, if it was captured.
+    pub fn output(&self) -> &[CapturedLine] {
+        match self {
+            Self::Operation { output, .. } => output,
+            _ => &[],
+        }
+    }
+
+    /// The operation this error occurred in, if known.
+    pub fn operation(&self) -> Option<&Operation> {
+        match self {
+            Self::Operation { operation, .. } => Some(operation),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for WhisperError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InitError => f.write_str("failed to create the context"),
+            Self::FailedToMapModel(e) => write!(f, "failed to map the model file: {}", e),
+            Self::SpectrogramNotInitialized => f.write_str("the spectrogram was not initialized"),
+            Self::EncodeNotComplete => f.write_str("encode was not called"),
+            Self::DecodeNotComplete => f.write_str("decode was not called"),
+            Self::UnableToCalculateSpectrogram => f.write_str("failed to compute the spectrogram"),
+            Self::UnableToCalculateEvaluation => f.write_str("failed to evaluate the model"),
+            Self::FailedToEncode => f.write_str("failed to run the encoder"),
+            Self::FailedToDecode => f.write_str("failed to run the decoder"),
+            Self::FailedToDetectLanguage => f.write_str("failed to auto-detect the language"),
+            Self::FailedToInitializeDecoders => f.write_str("failed to initialize the decoders"),
+            Self::AudioCtxTooLarge => {
+                f.write_str("the audio context is larger than the model supports")
+            }
+            Self::InvalidMelBands => f.write_str("invalid number of mel bands"),
+            Self::InvalidThreadCount => f.write_str("invalid thread count, must be at least 1"),
+            Self::NPastTooLarge { n_past, n_known } => write!(
+                f,
+                "n_past is {}, but only {} tokens were decoded",
+                n_past, n_known
+            ),
+            Self::InvalidUtf8 {
+                error_len: Some(len),
+                valid_up_to,
+            } => write!(
+                f,
+                "invalid UTF-8: {} invalid bytes after the first {} bytes",
+                len, valid_up_to
+            ),
+            Self::InvalidUtf8 {
+                error_len: None,
+                valid_up_to,
+            } => write!(
+                f,
+                "invalid UTF-8: incomplete character after the first {} bytes",
+                valid_up_to
+            ),
+            Self::NullByteInString { idx } => write!(f, "null byte in string at index {}", idx),
+            Self::NullPointer => f.write_str("whisper.cpp returned a null pointer"),
+            Self::GenericError(code) => write!(f, "whisper.cpp returned error code {}", code),
+            Self::InvalidText => f.write_str("failed to convert the text into tokens"),
+            Self::Aborted => f.write_str("the transcription was aborted"),
+            Self::Timeout { segments, position } => write!(
+                f,
+                "timed out after transcribing up to {} ({} segments)",
+                position,
+                segments.len()
+            ),
+            Self::Operation {
+                operation,
+                error,
+                output,
+            } => {
+                write!(f, "{}: {}", operation, error)?;
+                // the most severe line is usually the one explaining the failure
+                let reason = output
+                    .iter()
+                    .rev()
+                    .min_by_key(|line| line.level)
+                    .filter(|line| line.level <= CaptureLevel::Warn);
+                match reason {
+                    Some(line) => write!(f, " ({})", line.text.trim()),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+// `Display` already includes the wrapped error of `Operation`, so it is not returned from `source`
+// as well, which would make error reporters print it twice.
+impl std::error::Error for WhisperError {}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = |n_samples: usize| n_samples as f64 / crate::WHISPER_SAMPLE_RATE as f64;
+        match self {
+            Self::Init { path } => write!(f, "loading model from {:?}", path),
+            Self::InitFromBuffer { n_bytes } => {
+                write!(f, "loading model from a {} byte buffer", n_bytes)
+            }
+            Self::InitFromMmap { path } => write!(f, "loading memory-mapped model from {:?}", path),
+            Self::PcmToMel { n_samples, threads } => write!(
+                f,
+                "computing spectrogram of {} samples ({:.2} s) with {} threads",
+                n_samples,
+                secs(*n_samples),
+                threads
+            ),
+            Self::SetMel { n_len } => write!(f, "setting a spectrogram of {} values", n_len),
+            Self::Encode { offset, threads } => {
+                write!(f, "encoding at offset {} with {} threads", offset, threads)
+            }
+            Self::Decode {
+                n_tokens,
+                n_past,
+                threads,
+            } => write!(
+                f,
+                "decoding {} tokens with {} past tokens and {} threads",
+                n_tokens, n_past, threads
+            ),
+            Self::Tokenize { text_len } => write!(f, "tokenizing {} bytes of text", text_len),
+            Self::LangDetect { offset_ms, threads } => write!(
+                f,
+                "detecting language at {} ms with {} threads",
+                offset_ms, threads
+            ),
+            Self::Full { n_samples, threads } => write!(
+                f,
+                "transcribing {} samples ({:.2} s) with {} threads",
+                n_samples,
+                secs(*n_samples),
+                threads
+            ),
+            Self::FullParallel {
+                n_samples,
+                threads,
+                n_processors,
+            } => write!(
+                f,
+                "transcribing {} samples ({:.2} s) with {} threads on {} processors",
+                n_samples,
+                secs(*n_samples),
+                threads,
+                n_processors
+            ),
+            Self::TranscribeParallel { worker, start, end } => {
+                write!(f, "transcribing {} to {} on worker {}", start, end, worker)
+            }
+        }
+    }
 }
 
 impl From<Utf8Error> for WhisperError {
@@ -55,3 +322,47 @@ impl From<NulError> for WhisperError {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn operation_context() {
+        let error = WhisperError::from_full_code(-6).with_operation(
+            Operation::Full {
+                n_samples: 48000,
+                threads: 4,
+            },
+            None,
+        );
+        assert_eq!(error.root_cause(), &WhisperError::FailedToEncode);
+        assert!(std::error::Error::source(&error).is_none());
+        assert_eq!(
+            error.to_string(),
+            "transcribing 48000 samples (3.00 s) with 4 threads: failed to run the encoder"
+        );
+
+        let error = WhisperError::Operation {
+            operation: Operation::Init {
+                path: "model.bin".to_string(),
+            },
+            error: Box::new(WhisperError::InitError),
+            output: [
+                "whisper_model_load: loading model",
+                "whisper_model_load: invalid model data (bad magic)",
+            ]
+            .iter()
+            .map(|text| CapturedLine {
+                level: CaptureLevel::parse(text),
+                text: text.to_string(),
+            })
+            .collect(),
+        };
+        assert_eq!(
+            error.to_string(),
+            "loading model from \"model.bin\": failed to create the context \
+             (whisper_model_load: invalid model data (bad magic))"
+        );
+    }
+}
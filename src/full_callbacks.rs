@@ -0,0 +1,225 @@
+//! Rust-side callbacks of a single `whisper_full` call.
+
+use crate::progress::ProgressTracker;
+use crate::timestamp::WHISPER_SAMPLE_RATE;
+use crate::timings::Timings;
+use crate::whisper_ctx::WhisperContext;
+use crate::whisper_params::{FullParams, SegmentCallback};
+use crate::{WhisperNewSegmentCallback, WhisperStartEncoderCallback};
+use std::any::Any;
+use std::ffi::{c_int, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// State shared with the C callbacks during a `whisper_full` call.
+///
+/// Takes over the encoder begin and new segment callbacks of the params,
+/// and chains to the ones set by the user.
+pub(crate) struct FullCallbacks {
+    /// The context the call runs on, to copy out new segments.
+    ctx: *const WhisperContext,
+    start: Instant,
+    abort: Option<Arc<AtomicBool>>,
+    deadline: Option<Instant>,
+    /// Set once the call was aborted through the encoder begin callback.
+    aborted: AtomicBool,
+    /// Set if it was aborted because the deadline passed.
+    timed_out: AtomicBool,
+    /// (time of the first encoder run, number of encoder runs).
+    /// `whisper_full_parallel` may call the callbacks from several threads.
+    encodes: Mutex<(Option<Instant>, usize)>,
+    encoder_begin: WhisperStartEncoderCallback,
+    encoder_begin_user_data: *mut c_void,
+    on_segment: Option<Mutex<SegmentCallback>>,
+    progress: Option<Mutex<ProgressTracker>>,
+    new_segment: WhisperNewSegmentCallback,
+    new_segment_user_data: *mut c_void,
+    /// A panic in a Rust callback, resumed once whisper.cpp has returned.
+    panic: Mutex<Option<Box<dyn Any + Send>>>,
+}
+
+impl FullCallbacks {
+    /// Install the callbacks into `params`.
+    ///
+    /// # Safety
+    /// The returned box must not be dropped while the raw params are in use,
+    /// and `ctx` must not be moved or mutably borrowed in the meantime.
+    pub(crate) unsafe fn install(
+        params: &mut FullParams,
+        ctx: &WhisperContext,
+        n_samples: usize,
+    ) -> Box<Self> {
+        let on_segment = params.on_segment.take().map(Mutex::new);
+        let fp = &mut params.fp;
+        let progress = params.on_progress.take().map(|on_progress| {
+            Mutex::new(ProgressTracker::new(
+                on_progress,
+                fp.offset_ms,
+                fp.duration_ms,
+                samples_to_duration(n_samples),
+            ))
+        });
+        let callbacks = Box::new(Self {
+            ctx,
+            start: Instant::now(),
+            abort: params.abort.clone(),
+            deadline: params.deadline,
+            aborted: AtomicBool::new(false),
+            timed_out: AtomicBool::new(false),
+            encodes: Mutex::new((None, 0)),
+            encoder_begin: fp.encoder_begin_callback,
+            encoder_begin_user_data: fp.encoder_begin_callback_user_data,
+            on_segment,
+            progress,
+            new_segment: fp.new_segment_callback,
+            new_segment_user_data: fp.new_segment_callback_user_data,
+            panic: Mutex::new(None),
+        });
+        let user_data = &*callbacks as *const Self as *mut c_void;
+        fp.encoder_begin_callback = Some(encoder_begin);
+        fp.encoder_begin_callback_user_data = user_data;
+        fp.new_segment_callback = Some(new_segment);
+        fp.new_segment_callback_user_data = user_data;
+        callbacks
+    }
+
+    /// Re-raise a panic that happened inside a Rust callback.
+    pub(crate) fn resume_panic(&self) {
+        if let Some(payload) = self.panic.lock().unwrap_or_else(|e| e.into_inner()).take() {
+            panic::resume_unwind(payload);
+        }
+    }
+
+    /// Run a Rust callback, catching panics so they don't unwind into C.
+    fn guard(&self, f: impl FnOnce()) {
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(f)) {
+            self.panic
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get_or_insert(payload);
+        }
+    }
+
+    /// Report the whole audio as processed, once the call succeeded.
+    pub(crate) fn finish(&self) {
+        if let Some(progress) = &self.progress {
+            self.guard(|| progress.lock().unwrap_or_else(|e| e.into_inner()).finish());
+        }
+        self.resume_panic();
+    }
+
+    /// Was the call stopped early by the abort flag?
+    pub(crate) fn aborted(&self) -> bool {
+        self.aborted.load(Ordering::SeqCst)
+    }
+
+    /// Was the call stopped early because the deadline passed?
+    pub(crate) fn timed_out(&self) -> bool {
+        self.timed_out.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn timings(
+        &self,
+        load: Duration,
+        n_samples: usize,
+        encode_decode: Option<(Duration, Duration)>,
+    ) -> Timings {
+        let total = self.start.elapsed();
+        let (first_encode, n_windows) = *self.encodes.lock().unwrap_or_else(|e| e.into_inner());
+        let mel = first_encode.map_or(total, |t| t.duration_since(self.start));
+        Timings {
+            load,
+            mel,
+            encode: encode_decode.map(|(encode, _)| encode),
+            decode: encode_decode.map(|(_, decode)| decode),
+            total,
+            audio: samples_to_duration(n_samples),
+            n_windows,
+        }
+    }
+
+    fn should_abort(&self) -> bool {
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            self.timed_out.store(true, Ordering::SeqCst);
+            return true;
+        }
+        let panicked = self
+            .panic
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_some();
+        panicked
+            || self
+                .abort
+                .as_ref()
+                .is_some_and(|abort| abort.load(Ordering::SeqCst))
+    }
+}
+
+fn samples_to_duration(n_samples: usize) -> Duration {
+    Duration::from_secs_f64(n_samples as f64 / WHISPER_SAMPLE_RATE as f64)
+}
+
+unsafe extern "C" fn encoder_begin(
+    ctx: *mut whisper_rs_sys::whisper_context,
+    user_data: *mut c_void,
+) -> bool {
+    let callbacks = &*(user_data as *const FullCallbacks);
+    if callbacks.should_abort() {
+        callbacks.aborted.store(true, Ordering::SeqCst);
+        return false;
+    }
+    {
+        let mut encodes = callbacks.encodes.lock().unwrap_or_else(|e| e.into_inner());
+        encodes.0.get_or_insert_with(Instant::now);
+        encodes.1 += 1;
+    }
+    if let Some(progress) = &callbacks.progress {
+        callbacks.guard(|| progress.lock().unwrap_or_else(|e| e.into_inner()).report());
+    }
+    match callbacks.encoder_begin {
+        Some(callback) => callback(ctx, callbacks.encoder_begin_user_data),
+        None => true,
+    }
+}
+
+unsafe extern "C" fn new_segment(
+    ctx: *mut whisper_rs_sys::whisper_context,
+    n_new: c_int,
+    user_data: *mut c_void,
+) {
+    let callbacks = &*(user_data as *const FullCallbacks);
+    let whisper = &*callbacks.ctx;
+    // whisper_full_parallel also reports segments of its helper contexts, which are merged later
+    if let Some(on_segment) = callbacks.on_segment.as_ref().filter(|_| whisper.ctx == ctx) {
+        callbacks.guard(|| {
+            let mut on_segment = on_segment.lock().unwrap_or_else(|e| e.into_inner());
+            let n_segments = whisper.full_n_segments();
+            for i in (n_segments - n_new).max(0)..n_segments {
+                if let Ok(segment) = whisper.full_get_segment(i) {
+                    on_segment(segment);
+                }
+            }
+        });
+    }
+    if let Some(progress) = callbacks.progress.as_ref().filter(|_| whisper.ctx == ctx) {
+        callbacks.guard(|| {
+            let n_segments = whisper.full_n_segments();
+            if n_segments > 0 {
+                let t1 = whisper.full_get_segment_t1(n_segments - 1);
+                progress
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .advance(t1.as_duration());
+            }
+        });
+    }
+    if let Some(callback) = callbacks.new_segment {
+        callback(ctx, n_new, callbacks.new_segment_user_data);
+    }
+}
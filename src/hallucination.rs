@@ -0,0 +1,453 @@
+//! Post-processing filter for hallucinated segments.
+
+use crate::segment::Segment;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::io::Write;
+
+/// Phrases Whisper commonly produces over silence or music, used by default by [HallucinationFilter].
+///
+/// People also really say some of these, so they are only detected over quiet audio,
+/// see [HallucinationFilter::phrase_rms_threshold].
+pub const DEFAULT_HALLUCINATION_PHRASES: &[&str] = &[
+    "Thank you.",
+    "Thanks for watching!",
+    "Thank you for watching.",
+    "Thank you so much for watching!",
+    "Please subscribe to my channel.",
+    "Don't forget to like and subscribe!",
+    "Subtitles by the Amara.org community",
+    "Transcription by CastingWords",
+    "you",
+];
+
+/// What [HallucinationFilter] does with a segment it detects.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum FilterAction {
+    /// Remove the segment.
+    Drop,
+    /// Keep the segment unchanged, only list it in the [FilterReport].
+    Flag,
+    /// Cut repetition loops down to their first occurrence, removing the repeated tokens
+    /// and moving the end of the segment to the end of what is left.
+    /// Other detections are dropped, since there is nothing to cut.
+    Trim,
+}
+
+/// Why a segment was detected as a hallucination.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HallucinationKind {
+    /// A sequence of `ngram` words repeats `repeats` times in a row.
+    Repetition { ngram: usize, repeats: usize },
+    /// The text compresses unusually well, which is typical of looping output.
+    CompressionRatio(f32),
+    /// The audio under the segment is near silent. Holds the RMS level of the audio.
+    Silence(f32),
+    /// The text matches one of the configured phrases, over quiet audio.
+    KnownPhrase(String),
+}
+
+/// A single segment detected by [HallucinationFilter].
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterEntry {
+    /// Index of the segment in the input.
+    pub index: usize,
+    /// Text of the segment before filtering.
+    pub text: String,
+    /// Why the segment was detected.
+    pub kind: HallucinationKind,
+    /// What was done with the segment.
+    pub action: FilterAction,
+}
+
+/// Everything [HallucinationFilter::filter] detected, in input order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FilterReport {
+    /// One entry per detected segment.
+    pub entries: Vec<FilterEntry>,
+}
+
+impl FilterReport {
+    /// Entries for segments that were removed from the output.
+    pub fn removed(&self) -> impl Iterator<Item = &FilterEntry> {
+        self.entries
+            .iter()
+            .filter(|e| e.action == FilterAction::Drop)
+    }
+}
+
+/// Detects segments that Whisper most likely hallucinated, and drops, flags, or trims them.
+///
+/// The checks, in order of priority, are:
+/// * n-gram repetition loops ("Thank you. Thank you. Thank you.")
+/// * known hallucination phrases over quiet audio, if the original PCM audio is provided
+/// * an anomalous compression ratio, see https://github.com/openai/whisper/blob/f82bc59f5ea234d4b97fb2860842ed38519f7e65/whisper/transcribe.py#L274-L278
+/// * near silent audio under the segment, if the original PCM audio is provided
+#[derive(Debug, Clone)]
+pub struct HallucinationFilter {
+    /// What to do with detected segments.
+    ///
+    /// Defaults to [FilterAction::Drop].
+    pub action: FilterAction,
+    /// Longest word n-gram to look for repetitions of. 0 disables the check.
+    ///
+    /// Defaults to 8.
+    pub max_ngram: usize,
+    /// Number of consecutive occurrences of an n-gram that make a loop.
+    ///
+    /// Defaults to 3.
+    pub min_repeats: usize,
+    /// Segments whose text compresses better than this ratio are detected.
+    ///
+    /// Defaults to 2.4.
+    pub compression_ratio_threshold: f32,
+    /// Segments over audio with an RMS level below this are detected.
+    ///
+    /// Defaults to 0.005 (about -46 dBFS).
+    pub silence_rms_threshold: f32,
+    /// Phrases to detect. Matching ignores case, punctuation, and surrounding whitespace.
+    ///
+    /// Defaults to [DEFAULT_HALLUCINATION_PHRASES].
+    pub phrases: Vec<String>,
+    /// Segments matching one of the [phrases](HallucinationFilter::phrases) are only detected
+    /// over audio with an RMS level below this, so that real speech is kept.
+    /// Phrases are never detected without the PCM audio.
+    ///
+    /// Defaults to 0.01 (-40 dBFS).
+    pub phrase_rms_threshold: f32,
+}
+
+impl Default for HallucinationFilter {
+    fn default() -> Self {
+        Self {
+            action: FilterAction::Drop,
+            max_ngram: 8,
+            min_repeats: 3,
+            compression_ratio_threshold: 2.4,
+            silence_rms_threshold: 0.005,
+            phrases: DEFAULT_HALLUCINATION_PHRASES
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            phrase_rms_threshold: 0.01,
+        }
+    }
+}
+
+impl HallucinationFilter {
+    /// Filter a list of segments.
+    ///
+    /// # Arguments
+    /// * segments: The segments to filter, for example from [WhisperContext::full_get_segments](crate::WhisperContext::full_get_segments).
+    /// * pcm: The 16 kHz mono audio the segments were transcribed from. The silence check is skipped if this is None.
+    ///
+    /// # Returns
+    /// The segments that were kept, and a report of everything that was detected.
+    pub fn filter(
+        &self,
+        segments: Vec<Segment>,
+        pcm: Option<&[f32]>,
+    ) -> (Vec<Segment>, FilterReport) {
+        let mut kept = Vec::with_capacity(segments.len());
+        let mut report = FilterReport::default();
+        for (index, mut segment) in segments.into_iter().enumerate() {
+            let Some(kind) = self.check(&segment, pcm) else {
+                kept.push(segment);
+                continue;
+            };
+            let action = match (self.action, &kind) {
+                (FilterAction::Trim, HallucinationKind::Repetition { .. }) => FilterAction::Trim,
+                (FilterAction::Trim, _) => FilterAction::Drop,
+                (action, _) => action,
+            };
+            report.entries.push(FilterEntry {
+                index,
+                text: segment.text.clone(),
+                kind,
+                action,
+            });
+            match action {
+                FilterAction::Drop => {}
+                FilterAction::Flag => kept.push(segment),
+                FilterAction::Trim => {
+                    trim_segment(&mut segment, self.max_ngram, self.min_repeats);
+                    kept.push(segment);
+                }
+            }
+        }
+        (kept, report)
+    }
+
+    /// Check a single segment.
+    ///
+    /// # Returns
+    /// The reason the segment was detected, or None if it looks fine.
+    pub fn check(&self, segment: &Segment, pcm: Option<&[f32]>) -> Option<HallucinationKind> {
+        let words: Vec<&str> = segment.text.split_whitespace().collect();
+        if let Some((ngram, repeats)) = find_repetition(&words, self.max_ngram, self.min_repeats) {
+            return Some(HallucinationKind::Repetition { ngram, repeats });
+        }
+
+        let rms = pcm.and_then(|pcm| segment_rms(segment, pcm));
+        let normalized = normalize(&segment.text);
+        if rms.is_some_and(|rms| rms < self.phrase_rms_threshold)
+            && !normalized.is_empty()
+            && self.phrases.iter().any(|p| normalize(p) == normalized)
+        {
+            return Some(HallucinationKind::KnownPhrase(
+                segment.text.trim().to_string(),
+            ));
+        }
+
+        let ratio = compression_ratio(&segment.text);
+        if ratio > self.compression_ratio_threshold {
+            return Some(HallucinationKind::CompressionRatio(ratio));
+        }
+
+        if let Some(rms) = rms {
+            if rms < self.silence_rms_threshold {
+                return Some(HallucinationKind::Silence(rms));
+            }
+        }
+
+        None
+    }
+}
+
+/// Ratio of the size of `text` to the size of its zlib compressed form.
+pub fn compression_ratio(text: &str) -> f32 {
+    if text.is_empty() {
+        return 0.0;
+    }
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+    // writing to a Vec can't fail
+    encoder.write_all(text.as_bytes()).expect("write to Vec");
+    let compressed = encoder.finish().expect("write to Vec");
+    text.len() as f32 / compressed.len() as f32
+}
+
+/// Lowercase, drop punctuation, and collapse whitespace.
+fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Find the first n-gram (compared after normalization) that repeats at least `min_repeats` times in a row.
+///
+/// # Returns
+/// The start index of the loop, the n-gram size, and the number of repeats.
+fn find_loop(
+    words: &[&str],
+    max_ngram: usize,
+    min_repeats: usize,
+) -> Option<(usize, usize, usize)> {
+    if min_repeats < 2 {
+        return None;
+    }
+    let words: Vec<String> = words.iter().map(|w| normalize(w)).collect();
+    for start in 0..words.len() {
+        for n in 1..=max_ngram {
+            let mut repeats = 1;
+            while start + (repeats + 1) * n <= words.len()
+                && words[start..start + n] == words[start + repeats * n..start + (repeats + 1) * n]
+            {
+                repeats += 1;
+            }
+            if repeats >= min_repeats {
+                return Some((start, n, repeats));
+            }
+        }
+    }
+    None
+}
+
+fn find_repetition(words: &[&str], max_ngram: usize, min_repeats: usize) -> Option<(usize, usize)> {
+    find_loop(words, max_ngram, min_repeats).map(|(_, n, repeats)| (n, repeats))
+}
+
+/// Cut every repetition loop in `text` down to its first occurrence.
+fn trim_repetitions(text: &str, max_ngram: usize, min_repeats: usize) -> String {
+    let mut words: Vec<&str> = text.split_whitespace().collect();
+    while let Some((start, n, repeats)) = find_loop(&words, max_ngram, min_repeats) {
+        words.drain(start + n..start + n * repeats);
+    }
+    let trimmed = words.join(" ");
+    // keep the leading space whisper.cpp puts in front of segments
+    if text.starts_with(' ') {
+        format!(" {}", trimmed)
+    } else {
+        trimmed
+    }
+}
+
+/// Cut every repetition loop in the segment down to its first occurrence.
+///
+/// Works on the text tokens, grouped into words like [Segment::words], and rebuilds the text and end time
+/// from what is left. Segments without tokens only have their text trimmed.
+fn trim_segment(segment: &mut Segment, max_ngram: usize, min_repeats: usize) {
+    if !segment.tokens.iter().any(|token| token.kind.is_text()) {
+        segment.text = trim_repetitions(&segment.text, max_ngram, min_repeats);
+        return;
+    }
+
+    // bytes of every word, and the indices of its tokens
+    let mut words: Vec<(Vec<u8>, Vec<usize>)> = Vec::new();
+    for (i, token) in segment.tokens.iter().enumerate() {
+        if !token.kind.is_text() {
+            continue;
+        }
+        match words.last_mut() {
+            Some((bytes, tokens)) if token.bytes.first() != Some(&b' ') => {
+                bytes.extend_from_slice(&token.bytes);
+                tokens.push(i);
+            }
+            _ => words.push((token.bytes.clone(), vec![i])),
+        }
+    }
+    let mut removed = vec![false; segment.tokens.len()];
+    loop {
+        let texts: Vec<String> = words
+            .iter()
+            .map(|(bytes, _)| String::from_utf8_lossy(bytes).into_owned())
+            .collect();
+        let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+        let Some((start, n, repeats)) = find_loop(&texts, max_ngram, min_repeats) else {
+            break;
+        };
+        for (_, tokens) in words.drain(start + n..start + n * repeats) {
+            tokens.into_iter().for_each(|i| removed[i] = true);
+        }
+    }
+
+    segment.retain_text(|i, _| !removed[i]);
+}
+
+/// RMS level of the audio under the segment, or None if the segment is outside the audio.
+fn segment_rms(segment: &Segment, pcm: &[f32]) -> Option<f32> {
+    let start = segment.t0.to_sample().min(pcm.len());
+    let end = segment.t1.to_sample().min(pcm.len());
+    let samples = pcm.get(start..end).filter(|s| !s.is_empty())?;
+    let sum: f32 = samples.iter().map(|s| s * s).sum();
+    Some((sum / samples.len() as f32).sqrt())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::segment::SegmentToken;
+    use crate::{Timestamp, TokenKind};
+
+    fn segment(text: &str, t0: i64, t1: i64) -> Segment {
+        Segment {
+            t0: Timestamp::from_centis(t0),
+            t1: Timestamp::from_centis(t1),
+            text: text.to_string(),
+            tokens: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn detect_and_act() {
+        let pcm: Vec<f32> = (0..16000 * 5)
+            .map(|i| {
+                if i < 16000 * 3 {
+                    (i as f32 * 0.05).sin() * 0.5
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        let segments = vec![
+            segment(" The quick brown fox.", 0, 100),
+            segment(" Thank you. Thank you. Thank you. Thank you.", 100, 200),
+            segment(" Thank you.", 200, 300),
+            segment(" Thanks for watching!", 300, 400),
+            segment(" Some words over silence.", 400, 500),
+        ];
+
+        let filter = HallucinationFilter::default();
+        let (kept, report) = filter.filter(segments.clone(), Some(&pcm));
+        assert_eq!(kept.len(), 2);
+        assert_eq!(kept[0].text, " The quick brown fox.");
+        // said over speech, so kept
+        assert_eq!(kept[1].text, " Thank you.");
+        let kinds: Vec<_> = report.entries.iter().map(|e| e.kind.clone()).collect();
+        assert_eq!(
+            kinds[0],
+            HallucinationKind::Repetition {
+                ngram: 2,
+                repeats: 4
+            }
+        );
+        assert_eq!(
+            kinds[1],
+            HallucinationKind::KnownPhrase("Thanks for watching!".to_string())
+        );
+        assert!(matches!(kinds[2], HallucinationKind::Silence(_)));
+
+        let filter = HallucinationFilter {
+            action: FilterAction::Trim,
+            ..Default::default()
+        };
+        let (kept, report) = filter.filter(segments.clone(), Some(&pcm));
+        assert_eq!(kept.len(), 3);
+        assert_eq!(kept[1].text, " Thank you.");
+        assert_eq!(report.removed().count(), 2);
+
+        // phrases can't be told apart from speech without the audio
+        let (kept, _) = HallucinationFilter::default().filter(segments, None);
+        assert_eq!(kept.len(), 4);
+    }
+
+    #[test]
+    fn trim_tokens() {
+        let mut segment = segment(" Go on. Go on. Go on.", 0, 300);
+        let pieces: [&[u8]; 3] = [b" Go", b" on", b"."];
+        segment.tokens = pieces
+            .iter()
+            .cycle()
+            .take(9)
+            .enumerate()
+            .map(|(i, bytes)| {
+                let t0 = i as i64 * 30;
+                SegmentToken::for_test(i as i32, TokenKind::Text, bytes, 0.9, t0, t0 + 30)
+            })
+            .collect();
+        segment.tokens.push(SegmentToken::for_test(
+            50257,
+            TokenKind::Eot,
+            b"",
+            1.0,
+            300,
+            300,
+        ));
+
+        let filter = HallucinationFilter {
+            action: FilterAction::Trim,
+            ..Default::default()
+        };
+        let (kept, _) = filter.filter(vec![segment.clone()], None);
+        assert_eq!(kept[0].text, " Go on.");
+        assert_eq!(kept[0].tokens.len(), 4);
+        assert_eq!(kept[0].t1, Timestamp::from_centis(90));
+
+        // without token timestamps, the end moves by the share of text kept
+        for token in &mut segment.tokens {
+            token.data.t0 = -1;
+            token.data.t1 = -1;
+        }
+        let (kept, _) = filter.filter(vec![segment], None);
+        assert_eq!(kept[0].t1, Timestamp::from_centis(100));
+    }
+
+    #[test]
+    fn compression_ratio_of_loop() {
+        assert!(compression_ratio("I don't know. ".repeat(20).as_str()) > 2.4);
+        assert!(compression_ratio(" The quick brown fox jumps over the lazy dog.") < 2.4);
+    }
+}
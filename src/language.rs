@@ -0,0 +1,63 @@
+//! Languages and tasks known to whisper.cpp.
+
+use crate::standalone::{get_lang_id, get_lang_max_id, get_lang_str};
+use std::ffi::c_int;
+use std::fmt;
+
+/// A language supported by Whisper.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Language {
+    id: c_int,
+}
+
+impl Language {
+    /// Get the language with the given ID.
+    ///
+    /// # Returns
+    /// None if the ID is out of range.
+    pub fn from_id(id: c_int) -> Option<Self> {
+        (0..=get_lang_max_id()).contains(&id).then_some(Self { id })
+    }
+
+    /// The language with the given ID, which the caller has checked to be in range.
+    pub(crate) fn from_id_unchecked(id: c_int) -> Self {
+        Self { id }
+    }
+
+    /// Get the language with the given short code (e.g. "de") or full name (e.g. "german").
+    ///
+    /// # Returns
+    /// None if whisper.cpp doesn't know the language.
+    pub fn from_code(code: &str) -> Option<Self> {
+        if code.contains('\0') {
+            return None;
+        }
+        get_lang_id(code).map(|id| Self { id })
+    }
+
+    /// The whisper.cpp ID of this language.
+    #[inline]
+    pub fn id(&self) -> c_int {
+        self.id
+    }
+
+    /// The short code of this language (e.g. "de").
+    pub fn code(&self) -> &'static str {
+        get_lang_str(self.id).expect("language ID is in range")
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// The task the decoder is asked to perform.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Task {
+    /// Transcribe the audio in its spoken language.
+    Transcribe,
+    /// Translate the audio into English.
+    Translate,
+}
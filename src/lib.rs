@@ -1,15 +1,77 @@
 #![allow(clippy::uninlined_format_args)]
 #![cfg_attr(feature = "simd", feature(portable_simd))]
 
+#[cfg(feature = "tokio")]
+mod async_whisper;
+mod batch;
+mod bench;
+mod capture;
+mod confidence;
 mod error;
+mod full_callbacks;
+#[cfg(feature = "hallucination")]
+mod hallucination;
+mod language;
+mod logits;
+mod logits_processor;
+mod long_form;
+#[cfg(feature = "mmap")]
+mod mmap_loader;
+mod model_info;
+mod output;
+mod parallel;
+mod progress;
+mod segment;
 mod standalone;
+mod streaming;
+mod timestamp;
+mod timings;
+mod token_kind;
+mod tokenizer;
+mod transcript;
 mod utilities;
+mod verify;
+#[cfg(feature = "wav")]
+mod wav;
 mod whisper_ctx;
 mod whisper_params;
 
-pub use error::WhisperError;
+#[cfg(feature = "tokio")]
+pub use async_whisper::{AsyncWhisper, SegmentStream};
+pub use batch::{BatchError, BatchItem, BatchOutcome, BatchReport, BatchRunner};
+pub use bench::{bench_ggml_mul_mat, bench_memcpy, MemcpyBench, MulMatBench, MulMatBenchReport};
+#[cfg(unix)]
+pub use capture::OutputCapture;
+pub use capture::{CaptureConfig, CaptureLevel, CaptureSink, CapturedLine};
+pub use confidence::{ReviewPolicy, SegmentConfidence};
+pub use error::{Operation, WhisperError};
+#[cfg(feature = "hallucination")]
+pub use hallucination::{
+    compression_ratio, FilterAction, FilterEntry, FilterReport, HallucinationFilter,
+    HallucinationKind, DEFAULT_HALLUCINATION_PHRASES,
+};
+pub use language::{Language, Task};
+pub use logits::{Logits, LogitsRow};
+pub use logits_processor::{LogitsProcessor, NoRepeatNGram, SuppressTokens, TokenBias};
+pub use long_form::LongFormTranscriber;
+pub use model_info::{FileType, ModelInfo, ModelSize};
+pub use output::{json_number, json_string, OutputFormat};
+pub use parallel::{ContextPool, ParallelTranscript, WorkerTimings};
+pub use progress::Progress;
+pub use segment::{Segment, SegmentToken, Word};
 pub use standalone::*;
+pub use streaming::StreamingTranscription;
+pub use timestamp::{Timestamp, WHISPER_SAMPLE_RATE};
+pub use timings::Timings;
+pub use token_kind::TokenKind;
+pub use tokenizer::Tokenizer;
+pub use transcript::Transcript;
 pub use utilities::*;
+pub use verify::{verify_model, ModelError, OFFICIAL_MODEL_SHA1};
+#[cfg(feature = "checksum")]
+pub use verify::{verify_model_checksum, ModelChecksum};
+#[cfg(feature = "wav")]
+pub use wav::{decode_wav, is_wav, WavError};
 pub use whisper_ctx::WhisperContext;
 pub use whisper_params::{FullParams, SamplingStrategy};
 
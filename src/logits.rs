@@ -0,0 +1,173 @@
+//! Borrowed views over the logits produced by [WhisperContext::decode](crate::WhisperContext::decode).
+
+use crate::WhisperToken;
+use std::ops::Deref;
+
+/// The logits matrix from the last call to [WhisperContext::decode](crate::WhisperContext::decode).
+///
+/// Each row holds n_vocab logits. whisper.cpp only keeps the logits for the last token passed to decode,
+/// so there is a single row, holding the logits for the next token.
+///
+/// Obtained with [WhisperContext::logits](crate::WhisperContext::logits).
+/// This borrows the context, so it can't outlive the next decode call.
+#[derive(Debug, Copy, Clone)]
+pub struct Logits<'a> {
+    data: &'a [f32],
+    n_vocab: usize,
+}
+
+impl<'a> Logits<'a> {
+    pub(crate) fn new(data: &'a [f32], n_vocab: usize) -> Self {
+        Self { data, n_vocab }
+    }
+
+    /// Number of rows, 1 for the logits of [WhisperContext::logits](crate::WhisperContext::logits).
+    #[inline]
+    pub fn n_rows(&self) -> usize {
+        self.data.len().checked_div(self.n_vocab).unwrap_or(0)
+    }
+
+    /// Number of columns, ie the size of the vocabulary.
+    #[inline]
+    pub fn n_vocab(&self) -> usize {
+        self.n_vocab
+    }
+
+    /// Get the logits of row `i`.
+    ///
+    /// # Returns
+    /// None if `i` is out of bounds.
+    pub fn row(&self, i: usize) -> Option<LogitsRow<'a>> {
+        let start = i.checked_mul(self.n_vocab)?;
+        self.data
+            .get(start..start + self.n_vocab)
+            .map(|row| LogitsRow { row })
+    }
+
+    /// Get the logits for the next token.
+    ///
+    /// # Returns
+    /// None if there are no rows.
+    #[inline]
+    pub fn last(&self) -> Option<LogitsRow<'a>> {
+        self.n_rows().checked_sub(1).and_then(|i| self.row(i))
+    }
+
+    /// Iterate over all rows in order.
+    pub fn rows(&self) -> impl Iterator<Item = LogitsRow<'a>> + 'a {
+        self.data
+            .chunks_exact(self.n_vocab.max(1))
+            .map(|row| LogitsRow { row })
+    }
+
+    /// The entire matrix in row-major order.
+    #[inline]
+    pub fn as_slice(&self) -> &'a [f32] {
+        self.data
+    }
+}
+
+/// A single row of [Logits], indexed by token ID.
+///
+/// Derefs to `[f32]`.
+#[derive(Debug, Copy, Clone)]
+pub struct LogitsRow<'a> {
+    row: &'a [f32],
+}
+
+impl<'a> LogitsRow<'a> {
+    /// The token with the highest logit.
+    ///
+    /// # Returns
+    /// None if the row is empty.
+    pub fn argmax(&self) -> Option<WhisperToken> {
+        self.row
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(i, _)| i as WhisperToken)
+    }
+
+    /// Convert the logits into probabilities.
+    pub fn softmax(&self) -> Vec<f32> {
+        let max = self.max();
+        let mut probs: Vec<f32> = self.row.iter().map(|&x| (x - max).exp()).collect();
+        let sum: f32 = probs.iter().sum();
+        for p in &mut probs {
+            *p /= sum;
+        }
+        probs
+    }
+
+    /// Convert the logits into log probabilities.
+    ///
+    /// This is more accurate than taking the logarithm of [LogitsRow::softmax].
+    pub fn log_softmax(&self) -> Vec<f32> {
+        let max = self.max();
+        let log_sum = self.row.iter().map(|&x| (x - max).exp()).sum::<f32>().ln() + max;
+        self.row.iter().map(|&x| x - log_sum).collect()
+    }
+
+    /// The `k` tokens with the highest logits, in descending order.
+    ///
+    /// # Returns
+    /// Pairs of token ID and logit. Fewer than `k` if the row is shorter than `k`.
+    pub fn top_k(&self, k: usize) -> Vec<(WhisperToken, f32)> {
+        let mut top: Vec<(WhisperToken, f32)> = self
+            .row
+            .iter()
+            .enumerate()
+            .map(|(i, &x)| (i as WhisperToken, x))
+            .collect();
+        let k = k.min(top.len());
+        if k == 0 {
+            return Vec::new();
+        }
+        top.select_nth_unstable_by(k - 1, |a, b| b.1.total_cmp(&a.1));
+        top.truncate(k);
+        top.sort_unstable_by(|a, b| b.1.total_cmp(&a.1));
+        top
+    }
+
+    /// The row as a plain slice.
+    #[inline]
+    pub fn as_slice(&self) -> &'a [f32] {
+        self.row
+    }
+
+    fn max(&self) -> f32 {
+        self.row.iter().copied().fold(f32::NEG_INFINITY, f32::max)
+    }
+}
+
+impl Deref for LogitsRow<'_> {
+    type Target = [f32];
+
+    fn deref(&self) -> &[f32] {
+        self.row
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rows_and_helpers() {
+        let data = [0.0, 1.0, 2.0, 3.0, 2.0, 1.0];
+        let logits = Logits::new(&data, 3);
+        assert_eq!(logits.n_rows(), 2);
+        assert!(logits.row(2).is_none());
+
+        let last = logits.last().unwrap();
+        assert_eq!(&*last, &[3.0, 2.0, 1.0]);
+        assert_eq!(last.argmax(), Some(0));
+        assert_eq!(last.top_k(2), vec![(0, 3.0), (1, 2.0)]);
+
+        let probs = last.softmax();
+        assert!((probs.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+        for (p, lp) in probs.iter().zip(last.log_softmax()) {
+            assert!((p.ln() - lp).abs() < 1e-6);
+        }
+    }
+}
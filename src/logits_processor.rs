@@ -0,0 +1,189 @@
+//! Logits processors that are run after every call to [WhisperContext::decode](crate::WhisperContext::decode).
+
+use crate::WhisperToken;
+use std::collections::HashMap;
+
+/// Modifies the logits for the next token before they are used for sampling.
+///
+/// Processors are registered with [WhisperContext::add_logits_processor](crate::WhisperContext::add_logits_processor)
+/// and run in the order they were added, after every successful call to
+/// [WhisperContext::decode](crate::WhisperContext::decode).
+pub trait LogitsProcessor: Send {
+    /// Modify the logits for the next token in place.
+    ///
+    /// # Arguments
+    /// * history: Every token evaluated so far, ie the first `n_past` tokens of the previous history
+    ///   followed by the tokens passed to the last decode call.
+    /// * logits: The logits for the next token. Length is equal to n_vocab.
+    fn process(&mut self, history: &[WhisperToken], logits: &mut [f32]);
+}
+
+impl<F> LogitsProcessor for F
+where
+    F: FnMut(&[WhisperToken], &mut [f32]) + Send,
+{
+    fn process(&mut self, history: &[WhisperToken], logits: &mut [f32]) {
+        self(history, logits)
+    }
+}
+
+/// Prevents the given tokens from ever being sampled by setting their logits to negative infinity.
+///
+/// Useful for suppressing non-speech symbols, profanity, or timestamp tokens.
+#[derive(Debug, Clone, Default)]
+pub struct SuppressTokens {
+    tokens: Vec<WhisperToken>,
+}
+
+impl SuppressTokens {
+    /// Create a new processor suppressing the given tokens.
+    pub fn new(tokens: impl IntoIterator<Item = WhisperToken>) -> Self {
+        Self {
+            tokens: tokens.into_iter().collect(),
+        }
+    }
+
+    /// Suppress an additional token.
+    pub fn add_token(&mut self, token: WhisperToken) {
+        self.tokens.push(token);
+    }
+}
+
+impl LogitsProcessor for SuppressTokens {
+    fn process(&mut self, _history: &[WhisperToken], logits: &mut [f32]) {
+        for &token in &self.tokens {
+            if let Some(logit) = logits.get_mut(token as usize) {
+                *logit = f32::NEG_INFINITY;
+            }
+        }
+    }
+}
+
+/// Adds a fixed bias to the logits of single tokens or multi-token phrases.
+///
+/// Positive values make a token more likely, negative values make it less likely.
+/// A phrase bias is applied to the first token of the phrase, and to each following token
+/// of the phrase once the history ends with the tokens that precede it.
+#[derive(Debug, Clone, Default)]
+pub struct TokenBias {
+    tokens: HashMap<WhisperToken, f32>,
+    phrases: Vec<(Vec<WhisperToken>, f32)>,
+}
+
+impl TokenBias {
+    /// Create a new processor without any biases.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `bias` to the logit of `token`.
+    ///
+    /// Biases for the same token are summed.
+    pub fn add_token(&mut self, token: WhisperToken, bias: f32) {
+        *self.tokens.entry(token).or_insert(0.0) += bias;
+    }
+
+    /// Add `bias` to each token of `phrase` as it is being decoded.
+    ///
+    /// The phrase can be obtained with [WhisperContext::tokenize](crate::WhisperContext::tokenize).
+    /// Empty phrases are ignored.
+    pub fn add_phrase(&mut self, phrase: &[WhisperToken], bias: f32) {
+        if !phrase.is_empty() {
+            self.phrases.push((phrase.to_vec(), bias));
+        }
+    }
+}
+
+impl LogitsProcessor for TokenBias {
+    fn process(&mut self, history: &[WhisperToken], logits: &mut [f32]) {
+        for (&token, &bias) in &self.tokens {
+            if let Some(logit) = logits.get_mut(token as usize) {
+                *logit += bias;
+            }
+        }
+        for (phrase, bias) in &self.phrases {
+            for (prefix_len, &next) in phrase.iter().enumerate() {
+                if history.ends_with(&phrase[..prefix_len]) {
+                    if let Some(logit) = logits.get_mut(next as usize) {
+                        *logit += bias;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Prevents any n-gram of the given size from appearing twice in the history.
+///
+/// See https://huggingface.co/docs/transformers/internal/generation_utils#transformers.NoRepeatNGramLogitsProcessor
+/// for more information.
+#[derive(Debug, Clone)]
+pub struct NoRepeatNGram {
+    n: usize,
+}
+
+impl NoRepeatNGram {
+    /// Create a new processor banning repeated n-grams of size `n`.
+    ///
+    /// # Panics
+    /// Panics if `n` is 0.
+    pub fn new(n: usize) -> Self {
+        assert!(n > 0, "n-gram size must be at least 1");
+        Self { n }
+    }
+}
+
+impl LogitsProcessor for NoRepeatNGram {
+    fn process(&mut self, history: &[WhisperToken], logits: &mut [f32]) {
+        if history.len() + 1 < self.n {
+            return;
+        }
+        // the n - 1 tokens that the next token would complete an n-gram with
+        let prefix = &history[history.len() + 1 - self.n..];
+        for window in history.windows(self.n) {
+            if &window[..self.n - 1] == prefix {
+                if let Some(logit) = logits.get_mut(window[self.n - 1] as usize) {
+                    *logit = f32::NEG_INFINITY;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn suppress_tokens() {
+        let mut logits = vec![1.0; 4];
+        SuppressTokens::new([1, 3, 10]).process(&[], &mut logits);
+        assert_eq!(logits, [1.0, f32::NEG_INFINITY, 1.0, f32::NEG_INFINITY]);
+    }
+
+    #[test]
+    fn token_and_phrase_bias() {
+        let mut bias = TokenBias::new();
+        bias.add_token(0, 1.0);
+        bias.add_phrase(&[1, 2], 2.0);
+
+        let mut logits = vec![0.0; 4];
+        bias.process(&[3], &mut logits);
+        assert_eq!(logits, [1.0, 2.0, 0.0, 0.0]);
+
+        let mut logits = vec![0.0; 4];
+        bias.process(&[3, 1], &mut logits);
+        assert_eq!(logits, [1.0, 2.0, 2.0, 0.0]);
+    }
+
+    #[test]
+    fn no_repeat_ngram() {
+        let mut logits = vec![0.0; 5];
+        NoRepeatNGram::new(2).process(&[1, 2, 3, 1], &mut logits);
+        assert_eq!(logits, [0.0, 0.0, f32::NEG_INFINITY, 0.0, 0.0]);
+
+        let mut logits = vec![0.0; 5];
+        NoRepeatNGram::new(3).process(&[1, 2, 3, 1], &mut logits);
+        assert_eq!(logits, [0.0; 5]);
+    }
+}
@@ -0,0 +1,394 @@
+//! Chunked transcription of long audio, managed from Rust.
+
+use crate::error::WhisperError;
+use crate::segment::Segment;
+use crate::timestamp::{Timestamp, WHISPER_SAMPLE_RATE};
+use crate::timings::Timings;
+use crate::transcript::Transcript;
+use crate::whisper_ctx::WhisperContext;
+use crate::whisper_params::FullParams;
+use crate::WhisperToken;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+/// Length of the frames compared when looking for a quiet point to cut at: 10 ms.
+pub(crate) const FRAME_LEN: usize = WHISPER_SAMPLE_RATE / 100;
+
+/// Fewest tokens two chunks must have in common to be aligned on them.
+const MIN_ALIGNED_TOKENS: usize = 2;
+
+/// Transcribes long audio in chunks, one [WhisperContext::full] call per chunk.
+///
+/// Unlike [WhisperContext::full_parallel], chunks are cut at the quietest point near the target length
+/// rather than at fixed offsets, consecutive chunks overlap, and the end of the transcript so far
+/// is passed as prompt to the next chunk.
+/// Text transcribed twice in the overlap is removed by aligning the tokens of both chunks,
+/// falling back to cutting in the middle of the overlap if they have too few tokens in common.
+/// The resulting segments are ordered, and never overlap in time.
+#[derive(Debug, Clone)]
+pub struct LongFormTranscriber {
+    /// Target length of a chunk.
+    ///
+    /// Defaults to 30 seconds, the length of the window the model sees at once.
+    pub chunk_len: Duration,
+    /// How far before the target end of a chunk to look for the quietest point to cut at.
+    ///
+    /// Defaults to 5 seconds.
+    pub search_len: Duration,
+    /// Audio shared by consecutive chunks. Limited to half of a chunk.
+    ///
+    /// Defaults to 2 seconds.
+    pub overlap: Duration,
+    /// Number of text tokens from the end of the transcript so far to pass as prompt to the next chunk.
+    /// 0 disables carrying context between chunks.
+    ///
+    /// Defaults to 224, half the text context of the model, like the Python implementation.
+    pub prompt_tokens: usize,
+}
+
+impl Default for LongFormTranscriber {
+    fn default() -> Self {
+        Self {
+            chunk_len: Duration::from_secs(30),
+            search_len: Duration::from_secs(5),
+            overlap: Duration::from_secs(2),
+            prompt_tokens: 224,
+        }
+    }
+}
+
+impl LongFormTranscriber {
+    /// Transcribe `audio` chunk by chunk.
+    ///
+    /// # Arguments
+    /// * ctx: The context to run on.
+    /// * make_params: Creates the [FullParams] for every chunk.
+    ///   Their offset and duration are ignored. Their prompt tokens are only used for the first chunk
+    ///   if [prompt_tokens](LongFormTranscriber::prompt_tokens) is not 0.
+    ///   Callbacks see the segments of a single chunk, with times relative to the start of the chunk.
+    /// * audio: 16 kHz mono PCM audio data.
+    ///
+    /// # Returns
+    /// Ok([Transcript]) with absolute timestamps and the timings of all chunks added up,
+    /// Err(WhisperError) if any chunk failed.
+    pub fn transcribe<'a, 'b, F>(
+        &self,
+        ctx: &mut WhisperContext,
+        mut make_params: F,
+        audio: &[f32],
+    ) -> Result<Transcript, WhisperError>
+    where
+        F: FnMut() -> FullParams<'a, 'b>,
+    {
+        let start = Instant::now();
+        let mut segments: Vec<Segment> = Vec::new();
+        let mut timings = Timings {
+            audio: Duration::from_secs_f64(audio.len() as f64 / WHISPER_SAMPLE_RATE as f64),
+            ..Default::default()
+        };
+        let mut previous_end = 0;
+        for (i, chunk) in self.plan_chunks(audio).into_iter().enumerate() {
+            let prompt = prompt_tokens(&segments, self.prompt_tokens);
+            let mut params = make_params();
+            params.set_offset_ms(0);
+            params.set_duration_ms(0);
+            if i > 0 && self.prompt_tokens > 0 {
+                params.set_tokens(&prompt);
+                // the prompt already holds the context, don't let whisper.cpp add its own
+                params.set_no_context(true);
+            }
+
+            let chunk_timings = ctx.full(params, &audio[chunk.clone()])?;
+            timings.load = chunk_timings.load;
+            timings.add_part(&chunk_timings);
+
+            let offset = Timestamp::from_sample(chunk.start);
+            let mut new = ctx.full_get_segments()?;
+            new.iter_mut().for_each(|segment| shift(segment, offset));
+            let overlap = offset..Timestamp::from_sample(previous_end.max(chunk.start));
+            merge(&mut segments, new, overlap);
+            previous_end = chunk.end;
+        }
+        timings.total = start.elapsed();
+        Ok(Transcript { segments, timings })
+    }
+
+    /// Split audio into chunks, each ending at the quietest point within the search range before its target end.
+    fn plan_chunks(&self, audio: &[f32]) -> Vec<Range<usize>> {
+        let samples = |d: Duration| (d.as_secs_f64() * WHISPER_SAMPLE_RATE as f64) as usize;
+        let chunk_len = samples(self.chunk_len).max(FRAME_LEN);
+        let search_len = samples(self.search_len).min(chunk_len / 2);
+        let overlap = samples(self.overlap).min(chunk_len / 2);
+
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        while start < audio.len() {
+            let target = start + chunk_len;
+            if target >= audio.len() {
+                chunks.push(start..audio.len());
+                break;
+            }
+            let end = quietest_point(audio, target - search_len..target);
+            chunks.push(start..end);
+            start = end - overlap.min((end - start) / 2);
+        }
+        chunks
+    }
+}
+
+/// The middle of the 10 ms frame with the least energy within `range`.
+pub(crate) fn quietest_point(audio: &[f32], range: Range<usize>) -> usize {
+    (range.start..range.end.saturating_sub(FRAME_LEN - 1))
+        .step_by(FRAME_LEN)
+        .map(|frame| {
+            let energy: f32 = audio[frame..frame + FRAME_LEN].iter().map(|s| s * s).sum();
+            (frame + FRAME_LEN / 2, energy)
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map_or(range.end, |(point, _)| point)
+}
+
+/// Make the timestamps of a segment from a chunk absolute.
+pub(crate) fn shift(segment: &mut Segment, offset: Timestamp) {
+    segment.t0 = segment.t0 + offset;
+    segment.t1 = segment.t1 + offset;
+    for token in &mut segment.tokens {
+        // token times are -1 when token timestamps are disabled
+        if token.data.t0 >= 0 {
+            token.data.t0 += offset.as_centis();
+            token.data.t1 += offset.as_centis();
+        }
+    }
+}
+
+/// The last `n` text tokens of a transcript.
+fn prompt_tokens(segments: &[Segment], n: usize) -> Vec<WhisperToken> {
+    let mut tokens: Vec<WhisperToken> = segments
+        .iter()
+        .rev()
+        .flat_map(|segment| segment.tokens.iter().rev())
+        .filter(|token| token.kind.is_text())
+        .map(|token| token.id)
+        .take(n)
+        .collect();
+    tokens.reverse();
+    tokens
+}
+
+/// Positions of the text tokens of `segments`, as (segment index, token index).
+fn text_tokens(segments: &[Segment], range: Range<usize>) -> Vec<(usize, usize)> {
+    range
+        .flat_map(|i| {
+            segments[i]
+                .tokens
+                .iter()
+                .enumerate()
+                .filter(|(_, token)| token.kind.is_text())
+                .map(move |(j, _)| (i, j))
+        })
+        .collect()
+}
+
+fn has_text(segment: &Segment) -> bool {
+    segment.tokens.iter().any(|token| token.kind.is_text())
+}
+
+/// Longest run of equal tokens, as (start in `a`, start in `b`, length).
+fn longest_common_run(
+    a: &[(usize, usize)],
+    b: &[(usize, usize)],
+    token_a: impl Fn((usize, usize)) -> WhisperToken,
+    token_b: impl Fn((usize, usize)) -> WhisperToken,
+) -> (usize, usize, usize) {
+    let mut best = (0, 0, 0);
+    let mut lengths = vec![0; b.len() + 1];
+    for (i, &pos_a) in a.iter().enumerate() {
+        // iterate backwards, so lengths[j] still holds the value of the previous row
+        for j in (0..b.len()).rev() {
+            lengths[j + 1] = if token_a(pos_a) == token_b(b[j]) {
+                lengths[j] + 1
+            } else {
+                0
+            };
+            if lengths[j + 1] > best.2 {
+                best = (
+                    i + 1 - lengths[j + 1],
+                    j + 1 - lengths[j + 1],
+                    lengths[j + 1],
+                );
+            }
+        }
+    }
+    best
+}
+
+/// Append the segments of a new chunk, removing text transcribed twice within `overlap`.
+pub(crate) fn merge(segments: &mut Vec<Segment>, mut new: Vec<Segment>, overlap: Range<Timestamp>) {
+    if !segments.is_empty() && overlap.start < overlap.end {
+        let tail_start = segments
+            .iter()
+            .rposition(|segment| segment.t1 <= overlap.start)
+            .map_or(0, |i| i + 1);
+        let head_end = new
+            .iter()
+            .position(|segment| segment.t0 >= overlap.end)
+            .unwrap_or(new.len());
+        let tail = text_tokens(segments, tail_start..segments.len());
+        let head = text_tokens(&new, 0..head_end);
+        let (a, b, len) = longest_common_run(
+            &tail,
+            &head,
+            |(i, j)| segments[i].tokens[j].id,
+            |(i, j)| new[i].tokens[j].id,
+        );
+
+        if len >= MIN_ALIGNED_TOKENS {
+            // keep the aligned tokens from the earlier chunk, and what follows them from the new one
+            let (i, j) = tail[a + len - 1];
+            segments.truncate(i + 1);
+            segments[i].retain_text(|k, _| k <= j);
+
+            let (i, j) = head[b + len - 1];
+            new.drain(..i);
+            new[0].retain_text(|k, _| k > j);
+        } else {
+            let cut =
+                Timestamp::from_centis((overlap.start.as_centis() + overlap.end.as_centis()) / 2);
+            while segments.last().is_some_and(|s| s.t0 >= cut) {
+                segments.pop();
+            }
+            if let Some(last) = segments.last_mut() {
+                last.retain_text(|_, time| time.start < cut);
+                if !has_text(last) {
+                    segments.pop();
+                }
+            }
+            // a segment across the cut keeps its words after it
+            new.retain(|segment| segment.t1 > cut);
+            for segment in new.iter_mut().take_while(|segment| segment.t0 < cut) {
+                segment.retain_text(|_, time| time.start >= cut);
+            }
+        }
+    }
+
+    for mut segment in new.into_iter().filter(has_text) {
+        if let Some(last) = segments.last() {
+            segment.t0 = segment.t0.max(last.t1);
+            segment.t1 = segment.t1.max(segment.t0);
+        }
+        segments.push(segment);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::segment::SegmentToken;
+    use crate::token_kind::TokenKind;
+
+    fn segment(t0: i64, t1: i64, words: &[(WhisperToken, &str)]) -> Segment {
+        let tokens: Vec<SegmentToken> = words
+            .iter()
+            .map(|&(id, text)| {
+                SegmentToken::for_test(id, TokenKind::Text, text.as_bytes(), 1.0, -1, -1)
+            })
+            .collect();
+        let mut segment = Segment {
+            t0: Timestamp::from_centis(t0),
+            t1: Timestamp::from_centis(t1),
+            text: String::new(),
+            tokens,
+        };
+        segment.rebuild_text();
+        segment
+    }
+
+    #[test]
+    fn cuts_at_quiet_points() {
+        let second = WHISPER_SAMPLE_RATE;
+        let mut audio = vec![0.5; 70 * second];
+        // a pause at 27 s, within the search range of the first chunk
+        audio[27 * second..27 * second + FRAME_LEN * 3].fill(0.0);
+        let transcriber = LongFormTranscriber::default();
+        let chunks = transcriber.plan_chunks(&audio);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0], 0..27 * second + FRAME_LEN / 2);
+        assert_eq!(chunks[1].start, chunks[0].end - 2 * second);
+        assert_eq!(chunks[2].end, audio.len());
+        assert!(transcriber.plan_chunks(&[]).is_empty());
+    }
+
+    #[test]
+    fn merges_on_aligned_tokens() {
+        let mut segments = vec![
+            segment(0, 1000, &[(1, " the"), (2, " quick")]),
+            segment(1000, 2900, &[(3, " brown"), (4, " fo")]),
+        ];
+        let new = vec![
+            segment(2700, 3200, &[(3, " brown"), (5, " fox"), (6, " jumps")]),
+            segment(3200, 4000, &[(7, " over")]),
+        ];
+        merge(
+            &mut segments,
+            new,
+            Timestamp::from_centis(2700)..Timestamp::from_centis(2900),
+        );
+        // only one token in common, so cut in the middle of the overlap, keeping the words after it
+        let texts: Vec<&str> = segments.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(texts, [" the quick", " brown fo", " fox jumps", " over"]);
+        // " fox" starts 6/16 into the segment, at 28.87 s, but not before " fo" ends
+        assert_eq!(segments[2].t0, Timestamp::from_centis(2900));
+        assert_eq!(segments[2].t1, Timestamp::from_centis(3200));
+
+        let mut segments = vec![segment(
+            0,
+            2900,
+            &[(1, " the"), (2, " quick"), (3, " brown"), (4, " fo")],
+        )];
+        let new = vec![segment(
+            2700,
+            3500,
+            &[(2, " quick"), (3, " brown"), (5, " fox")],
+        )];
+        merge(
+            &mut segments,
+            new,
+            Timestamp::from_centis(2700)..Timestamp::from_centis(2900),
+        );
+        let text: String = segments.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(text, " the quick brown fox");
+        assert!(segments.windows(2).all(|w| w[0].t1 <= w[1].t0));
+        // the earlier segment ends after " brown", 16/19 into it, and the new one starts at " fox", 12/16 into it
+        assert_eq!(segments[0].t1, Timestamp::from_centis(2442));
+        assert_eq!(segments[1].t0, Timestamp::from_centis(3300));
+        assert_eq!(prompt_tokens(&segments, 2), [3, 5]);
+    }
+
+    #[test]
+    fn drops_segments_after_the_cut() {
+        // " um" starts after the cut according to its token timestamps, although the segment starts before it
+        let mut late = segment(2750, 2900, &[(9, " um")]);
+        late.tokens[0] = SegmentToken::for_test(9, TokenKind::Text, b" um", 1.0, 2850, 2900);
+        let mut segments = vec![segment(0, 2700, &[(1, " the"), (2, " quick")]), late];
+        let overlap = Timestamp::from_centis(2700)..Timestamp::from_centis(2900);
+        merge(
+            &mut segments,
+            vec![segment(2800, 3500, &[(5, " fox")])],
+            overlap.clone(),
+        );
+        let texts: Vec<&str> = segments.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(texts, [" the quick", " fox"]);
+        assert_eq!(segments[1].t0, Timestamp::from_centis(2800));
+
+        // the only earlier segment lies after the cut
+        let mut segments = vec![segment(2850, 2900, &[(9, " um")])];
+        merge(
+            &mut segments,
+            vec![segment(2800, 3500, &[(5, " fox")])],
+            overlap,
+        );
+        let texts: Vec<&str> = segments.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(texts, [" fox"]);
+        assert_eq!(segments[0].t0, Timestamp::from_centis(2800));
+    }
+}
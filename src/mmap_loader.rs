@@ -0,0 +1,150 @@
+//! A `whisper_model_loader` reading from a memory-mapped model file.
+
+use memmap2::Mmap;
+use std::ffi::c_void;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// Pages behind the read position are released in steps of at least this many bytes.
+#[cfg(unix)]
+const RELEASE_STEP: usize = 1 << 20;
+
+/// A model file mapped into memory, read through the callbacks of a `whisper_model_loader`.
+pub(crate) struct MmapLoader {
+    map: Mmap,
+    position: usize,
+    /// Everything before this offset was released from the memory of the process.
+    #[cfg(unix)]
+    released: usize,
+    #[cfg(unix)]
+    page_size: usize,
+}
+
+impl MmapLoader {
+    /// Map the file at `path`. The pages are only read from disk once whisper.cpp copies them into its tensors.
+    pub(crate) fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // Safety: the file must not be truncated while mapped, which is the caller's responsibility
+        // just like for any other way of reading it.
+        let map = unsafe { Mmap::map(&file)? };
+        // the model is read once from start to end, so read ahead aggressively
+        #[cfg(unix)]
+        let _ = map.advise(memmap2::Advice::Sequential);
+        Ok(Self {
+            map,
+            position: 0,
+            #[cfg(unix)]
+            released: 0,
+            #[cfg(unix)]
+            page_size: unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(1) as usize,
+        })
+    }
+
+    /// Drop the pages that were already copied out of the map from the memory of the process,
+    /// so they don't stay resident next to the tensors whisper.cpp copied them into.
+    #[cfg(unix)]
+    fn release_read_pages(&mut self) {
+        let end = self.position - self.position % self.page_size;
+        if end - self.released >= RELEASE_STEP || (end > self.released && end == self.map.len()) {
+            // Safety: nothing borrows the map, and the pages before the read position are never read again.
+            // They belong to a shared mapping of the file, so they would be read from the file again anyway.
+            let _ = unsafe {
+                self.map.unchecked_advise_range(
+                    memmap2::UncheckedAdvice::DontNeed,
+                    self.released,
+                    end - self.released,
+                )
+            };
+            self.released = end;
+        }
+    }
+
+    /// The loader to pass to `whisper_init`. It must not outlive `self`, which must not move while it is used.
+    pub(crate) fn loader(&mut self) -> whisper_rs_sys::whisper_model_loader {
+        whisper_rs_sys::whisper_model_loader {
+            context: self as *mut Self as *mut c_void,
+            read: Some(read),
+            eof: Some(eof),
+            close: Some(close),
+        }
+    }
+}
+
+/// Copy up to `read_size` bytes to `output`, returning how many were copied.
+unsafe extern "C" fn read(ctx: *mut c_void, output: *mut c_void, read_size: usize) -> usize {
+    let loader = &mut *(ctx as *mut MmapLoader);
+    let remaining = &loader.map[loader.position..];
+    let n = read_size.min(remaining.len());
+    std::ptr::copy_nonoverlapping(remaining.as_ptr(), output as *mut u8, n);
+    loader.position += n;
+    #[cfg(unix)]
+    loader.release_read_pages();
+    n
+}
+
+unsafe extern "C" fn eof(ctx: *mut c_void) -> bool {
+    let loader = &*(ctx as *const MmapLoader);
+    loader.position >= loader.map.len()
+}
+
+/// Called by whisper.cpp once it is done loading. The map is unmapped when the [MmapLoader] is dropped.
+unsafe extern "C" fn close(_ctx: *mut c_void) {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn read_through_callbacks() {
+        let path = std::env::temp_dir().join(format!("whisper-rs-mmap-{}.bin", std::process::id()));
+        // a synthetic model of 4 MiB, with a pattern that catches misplaced reads
+        let model: Vec<u8> = (0..4 << 20).map(|i: u32| (i % 251) as u8).collect();
+        std::fs::write(&path, &model).unwrap();
+
+        let mut mapped = MmapLoader::open(&path).unwrap();
+        assert_eq!(mapped.map.len(), model.len());
+        let loader = mapped.loader();
+        let (read, eof) = (loader.read.unwrap(), loader.eof.unwrap());
+        let mut copy = Vec::with_capacity(model.len());
+        let mut buffer = vec![0u8; 100_003];
+        unsafe {
+            while !eof(loader.context) {
+                let n = read(
+                    loader.context,
+                    buffer.as_mut_ptr() as *mut c_void,
+                    buffer.len(),
+                );
+                assert!(n > 0);
+                copy.extend_from_slice(&buffer[..n]);
+            }
+            assert_eq!(
+                read(loader.context, buffer.as_mut_ptr() as *mut c_void, 4),
+                0
+            );
+            loader.close.unwrap()(loader.context);
+        }
+        assert!(copy == model);
+        // the pages that were read were released again
+        #[cfg(target_os = "linux")]
+        assert!(resident_kb(mapped.map.as_ptr()).unwrap() < 64);
+        drop(mapped);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// Resident size of the mapping starting at `start`, from /proc/self/smaps.
+    #[cfg(target_os = "linux")]
+    fn resident_kb(start: *const u8) -> Option<usize> {
+        let smaps = std::fs::read_to_string("/proc/self/smaps").ok()?;
+        let header = format!("{:x}-", start as usize);
+        smaps
+            .lines()
+            .skip_while(|line| !line.starts_with(&header))
+            .find_map(|line| line.strip_prefix("Rss:"))?
+            .trim()
+            .strip_suffix("kB")?
+            .trim()
+            .parse()
+            .ok()
+    }
+}
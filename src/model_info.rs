@@ -0,0 +1,275 @@
+//! Metadata of a ggml model file, read without loading the weights.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+/// Magic number at the start of a ggml model file, stored little endian. Spells "ggml" in ASCII.
+pub(crate) const GGML_MAGIC: u32 = 0x6767_6d6c;
+
+/// Vocabulary size of the English-only models. Multilingual models have more tokens.
+const N_VOCAB_ENGLISH: i32 = 51864;
+
+/// Factor the quantization version is multiplied by when stored in the file type field.
+const QNT_VERSION_FACTOR: i32 = 1000;
+
+/// The data type of the weights of a model.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum FileType {
+    /// All weights are 32 bit floats.
+    F32,
+    /// Mostly 16 bit floats.
+    F16,
+    /// Mostly 4 bit quantized, type 0.
+    Q4_0,
+    /// Mostly 4 bit quantized, type 1.
+    Q4_1,
+    /// Mostly 5 bit quantized, type 0.
+    Q5_0,
+    /// Mostly 5 bit quantized, type 1.
+    Q5_1,
+    /// Mostly 8 bit quantized.
+    Q8_0,
+    /// A type this version of whisper-rs doesn't know.
+    Other(i32),
+}
+
+impl FileType {
+    fn from_raw(ftype: i32) -> Self {
+        match ftype {
+            0 => Self::F32,
+            1 => Self::F16,
+            2 => Self::Q4_0,
+            3 => Self::Q4_1,
+            7 => Self::Q8_0,
+            8 => Self::Q5_0,
+            9 => Self::Q5_1,
+            other => Self::Other(other),
+        }
+    }
+}
+
+impl fmt::Display for FileType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::F32 => f.write_str("f32"),
+            Self::F16 => f.write_str("f16"),
+            Self::Q4_0 => f.write_str("q4_0"),
+            Self::Q4_1 => f.write_str("q4_1"),
+            Self::Q5_0 => f.write_str("q5_0"),
+            Self::Q5_1 => f.write_str("q5_1"),
+            Self::Q8_0 => f.write_str("q8_0"),
+            Self::Other(ftype) => write!(f, "unknown ({})", ftype),
+        }
+    }
+}
+
+/// Size class of a Whisper model, determined by the number of encoder layers.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ModelSize {
+    /// 4 layers, 39M parameters.
+    Tiny,
+    /// 6 layers, 74M parameters.
+    Base,
+    /// 12 layers, 244M parameters.
+    Small,
+    /// 24 layers, 769M parameters.
+    Medium,
+    /// 32 layers, 1550M parameters.
+    Large,
+}
+
+impl ModelSize {
+    /// The size class with this many encoder layers, if it is one of the released models.
+    pub fn from_n_audio_layer(n_audio_layer: i32) -> Option<Self> {
+        match n_audio_layer {
+            4 => Some(Self::Tiny),
+            6 => Some(Self::Base),
+            12 => Some(Self::Small),
+            24 => Some(Self::Medium),
+            32 => Some(Self::Large),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ModelSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Tiny => "tiny",
+            Self::Base => "base",
+            Self::Small => "small",
+            Self::Medium => "medium",
+            Self::Large => "large",
+        })
+    }
+}
+
+/// Hyperparameters and other metadata of a ggml model file.
+///
+/// Reading them only parses the header, so it is cheap even for large models, and fails with a
+/// descriptive error where [WhisperContext::new](crate::WhisperContext::new) only reports
+/// [InitError](crate::WhisperError::InitError).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelInfo {
+    /// Number of tokens in the vocabulary.
+    pub n_vocab: i32,
+    /// Number of audio positions the encoder sees, 1500 for 30 seconds.
+    pub n_audio_ctx: i32,
+    /// Width of the encoder.
+    pub n_audio_state: i32,
+    /// Number of attention heads of the encoder.
+    pub n_audio_head: i32,
+    /// Number of encoder layers.
+    pub n_audio_layer: i32,
+    /// Number of text positions the decoder sees.
+    pub n_text_ctx: i32,
+    /// Width of the decoder.
+    pub n_text_state: i32,
+    /// Number of attention heads of the decoder.
+    pub n_text_head: i32,
+    /// Number of decoder layers.
+    pub n_text_layer: i32,
+    /// Number of mel bands of the spectrogram.
+    pub n_mels: i32,
+    /// Data type of the weights.
+    pub ftype: FileType,
+    /// Version of the quantization format, 0 for unquantized and older files.
+    pub quantization_version: i32,
+    /// Size of the file in bytes. 0 when read with [ModelInfo::from_reader].
+    pub file_size: u64,
+}
+
+impl ModelInfo {
+    /// Read the header of the model file at `path`.
+    ///
+    /// # Returns
+    /// Ok(Self) on success. Err with [io::ErrorKind::InvalidData] if the file is not a ggml model,
+    /// or any other error from reading the file.
+    pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let file_size = file.metadata()?.len();
+        let mut info = Self::from_reader(BufReader::new(file))?;
+        info.file_size = file_size;
+        Ok(info)
+    }
+
+    /// Read the header of a model from `reader`, which is left positioned after the hyperparameters.
+    pub fn from_reader<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut read_i32 = || -> io::Result<i32> {
+            let mut bytes = [0; 4];
+            reader.read_exact(&mut bytes)?;
+            Ok(i32::from_le_bytes(bytes))
+        };
+        let magic = read_i32()? as u32;
+        if magic != GGML_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "not a ggml model: bad magic {:#010x}, expected {:#010x}",
+                    magic, GGML_MAGIC
+                ),
+            ));
+        }
+        // fields are read in the order they are written
+        let mut info = Self {
+            n_vocab: read_i32()?,
+            n_audio_ctx: read_i32()?,
+            n_audio_state: read_i32()?,
+            n_audio_head: read_i32()?,
+            n_audio_layer: read_i32()?,
+            n_text_ctx: read_i32()?,
+            n_text_state: read_i32()?,
+            n_text_head: read_i32()?,
+            n_text_layer: read_i32()?,
+            n_mels: read_i32()?,
+            ftype: FileType::F32,
+            quantization_version: 0,
+            file_size: 0,
+        };
+        let ftype = read_i32()?;
+        info.ftype = FileType::from_raw(ftype % QNT_VERSION_FACTOR);
+        info.quantization_version = ftype / QNT_VERSION_FACTOR;
+
+        let dims = [
+            info.n_vocab,
+            info.n_audio_ctx,
+            info.n_audio_state,
+            info.n_audio_head,
+            info.n_audio_layer,
+            info.n_text_ctx,
+            info.n_text_state,
+            info.n_text_head,
+            info.n_text_layer,
+            info.n_mels,
+        ];
+        if dims.iter().any(|&dim| dim <= 0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid model hyperparameters: {:?}", dims),
+            ));
+        }
+        Ok(info)
+    }
+
+    /// The size class of the model, or None if its number of encoder layers doesn't match a released model.
+    pub fn size(&self) -> Option<ModelSize> {
+        ModelSize::from_n_audio_layer(self.n_audio_layer)
+    }
+
+    /// Can the model transcribe languages other than English?
+    /// English-only models have a smaller vocabulary without the language tokens.
+    pub fn is_multilingual(&self) -> bool {
+        self.n_vocab > N_VOCAB_ENGLISH
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The header of ggml-base.en.bin.
+    fn base_en_header() -> Vec<u8> {
+        [
+            GGML_MAGIC as i32,
+            51864,
+            1500,
+            512,
+            8,
+            6,
+            448,
+            512,
+            8,
+            6,
+            80,
+            1,
+        ]
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect()
+    }
+
+    #[test]
+    fn read_header() {
+        let info = ModelInfo::from_reader(base_en_header().as_slice()).unwrap();
+        assert_eq!(info.n_audio_state, 512);
+        assert_eq!(info.n_text_ctx, 448);
+        assert_eq!(info.n_mels, 80);
+        assert_eq!(info.ftype, FileType::F16);
+        assert_eq!(info.size(), Some(ModelSize::Base));
+        assert!(!info.is_multilingual());
+
+        let mut quantized = base_en_header();
+        quantized[4..8].copy_from_slice(&51865i32.to_le_bytes());
+        quantized[44..48].copy_from_slice(&1008i32.to_le_bytes());
+        let info = ModelInfo::from_reader(quantized.as_slice()).unwrap();
+        assert!(info.is_multilingual());
+        assert_eq!((info.ftype, info.quantization_version), (FileType::Q5_0, 1));
+
+        let error = ModelInfo::from_reader(&b"GGUF\0\0\0\0"[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let error = ModelInfo::from_reader(&base_en_header()[..20]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
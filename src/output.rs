@@ -0,0 +1,247 @@
+//! Rendering of transcription results as text, subtitles or JSON.
+
+use crate::segment::Segment;
+use crate::timestamp::Timestamp;
+use std::fmt::{self, Write as _};
+use std::io;
+use std::str::FromStr;
+
+/// A format to write transcribed [Segment]s in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum OutputFormat {
+    /// Plain text, one segment per line.
+    Txt,
+    /// SubRip subtitles.
+    Srt,
+    /// WebVTT subtitles.
+    Vtt,
+    /// JSON with the full text, and the timestamps, text and words of every segment.
+    Json,
+}
+
+impl OutputFormat {
+    /// Every supported format.
+    pub const ALL: [Self; 4] = [Self::Txt, Self::Srt, Self::Vtt, Self::Json];
+
+    /// The usual file extension of the format, without the leading dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Txt => "txt",
+            Self::Srt => "srt",
+            Self::Vtt => "vtt",
+            Self::Json => "json",
+        }
+    }
+
+    /// The MIME type of the format.
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Self::Txt => "text/plain; charset=utf-8",
+            Self::Srt => "application/x-subrip",
+            Self::Vtt => "text/vtt",
+            Self::Json => "application/json",
+        }
+    }
+
+    /// Render segments in this format.
+    pub fn render(self, segments: &[Segment]) -> String {
+        let mut out = String::new();
+        // writing to a String can't fail
+        let _ = match self {
+            Self::Txt => render_txt(&mut out, segments),
+            Self::Srt => render_srt(&mut out, segments),
+            Self::Vtt => render_vtt(&mut out, segments),
+            Self::Json => render_json(&mut out, segments),
+        };
+        out
+    }
+
+    /// Write segments in this format.
+    pub fn write<W: io::Write>(self, segments: &[Segment], mut writer: W) -> io::Result<()> {
+        writer.write_all(self.render(segments).as_bytes())
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    /// Parse a format from its extension, ignoring case. `text` is accepted for [OutputFormat::Txt].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "txt" | "text" => Ok(Self::Txt),
+            "srt" => Ok(Self::Srt),
+            "vtt" => Ok(Self::Vtt),
+            "json" => Ok(Self::Json),
+            _ => Err(format!(
+                "unknown output format {:?}, expected one of txt, srt, vtt, json",
+                s
+            )),
+        }
+    }
+}
+
+fn render_txt(out: &mut String, segments: &[Segment]) -> fmt::Result {
+    for segment in segments {
+        writeln!(out, "{}", segment.text.trim())?;
+    }
+    Ok(())
+}
+
+fn render_srt(out: &mut String, segments: &[Segment]) -> fmt::Result {
+    for (i, segment) in segments.iter().enumerate() {
+        writeln!(
+            out,
+            "{}\n{} --> {}\n{}\n",
+            i + 1,
+            srt_time(segment.t0),
+            srt_time(segment.t1),
+            segment.text.trim()
+        )?;
+    }
+    Ok(())
+}
+
+fn render_vtt(out: &mut String, segments: &[Segment]) -> fmt::Result {
+    writeln!(out, "WEBVTT\n")?;
+    for segment in segments {
+        writeln!(
+            out,
+            "{} --> {}\n{}\n",
+            segment.t0,
+            segment.t1,
+            segment.text.trim()
+        )?;
+    }
+    Ok(())
+}
+
+fn render_json(out: &mut String, segments: &[Segment]) -> fmt::Result {
+    let text: String = segments.iter().map(|s| s.text.as_str()).collect();
+    write!(
+        out,
+        "{{\"text\":{},\"segments\":[",
+        json_string(text.trim())
+    )?;
+    for (i, segment) in segments.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write!(
+            out,
+            "{{\"id\":{},\"start\":{},\"end\":{},\"text\":{},\"words\":[",
+            i,
+            segment.t0.as_secs_f64(),
+            segment.t1.as_secs_f64(),
+            json_string(segment.text.trim())
+        )?;
+        for (j, word) in segment.words().iter().enumerate() {
+            if j > 0 {
+                out.push(',');
+            }
+            write!(
+                out,
+                "{{\"word\":{},\"start\":{},\"end\":{},\"probability\":{}}}",
+                json_string(&word.text),
+                json_time(word.t0),
+                json_time(word.t1),
+                json_number(Some(word.probability as f64))
+            )?;
+        }
+        write!(out, "]}}")?;
+    }
+    writeln!(out, "]}}")
+}
+
+/// SRT uses a comma as the decimal separator.
+fn srt_time(timestamp: Timestamp) -> String {
+    timestamp.to_string().replace('.', ",")
+}
+
+fn json_time(timestamp: Option<Timestamp>) -> String {
+    timestamp.map_or("null".to_string(), |t| t.as_secs_f64().to_string())
+}
+
+/// Format a number for JSON, as in [OutputFormat::Json].
+///
+/// # Returns
+/// The number, or `null` if it is missing or not finite.
+pub fn json_number(value: Option<f64>) -> String {
+    match value {
+        Some(v) if v.is_finite() => v.to_string(),
+        _ => "null".to_string(),
+    }
+}
+
+/// Quote and escape a string for JSON, as in [OutputFormat::Json].
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn segments() -> Vec<Segment> {
+        vec![
+            Segment {
+                t0: Timestamp::from_centis(0),
+                t1: Timestamp::from_centis(250),
+                text: " Hello there.".to_string(),
+                tokens: Vec::new(),
+            },
+            Segment {
+                t0: Timestamp::from_centis(250),
+                t1: Timestamp::from_centis(6105),
+                text: " He said \"hi\".".to_string(),
+                tokens: Vec::new(),
+            },
+        ]
+    }
+
+    #[test]
+    fn render_formats() {
+        let segments = segments();
+        assert_eq!(
+            OutputFormat::Txt.render(&segments),
+            "Hello there.\nHe said \"hi\".\n"
+        );
+        assert_eq!(
+            OutputFormat::Srt.render(&segments),
+            "1\n00:00:00,000 --> 00:00:02,500\nHello there.\n\n\
+             2\n00:00:02,500 --> 00:01:01,050\nHe said \"hi\".\n\n"
+        );
+        assert!(OutputFormat::Vtt
+            .render(&segments)
+            .starts_with("WEBVTT\n\n00:00:00.000 --> 00:00:02.500\nHello there.\n\n"));
+        assert_eq!(
+            OutputFormat::Json.render(&segments),
+            "{\"text\":\"Hello there. He said \\\"hi\\\".\",\"segments\":[\
+             {\"id\":0,\"start\":0,\"end\":2.5,\"text\":\"Hello there.\",\"words\":[]},\
+             {\"id\":1,\"start\":2.5,\"end\":61.05,\"text\":\"He said \\\"hi\\\".\",\"words\":[]}]}\n"
+        );
+        assert_eq!("SRT".parse(), Ok(OutputFormat::Srt));
+        assert!("doc".parse::<OutputFormat>().is_err());
+    }
+}
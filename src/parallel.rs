@@ -0,0 +1,241 @@
+//! Parallel transcription on several contexts.
+
+use crate::error::{Operation, WhisperError};
+use crate::long_form::{merge, quietest_point, shift, FRAME_LEN};
+use crate::segment::Segment;
+use crate::timestamp::{Timestamp, WHISPER_SAMPLE_RATE};
+use crate::timings::Timings;
+use crate::transcript::Transcript;
+use crate::whisper_ctx::WhisperContext;
+use crate::whisper_params::FullParams;
+use std::ops::Range;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Longest distance from an even split to look for a quiet point to cut at.
+const MAX_SEARCH_LEN: usize = 5 * WHISPER_SAMPLE_RATE;
+
+/// A set of contexts to transcribe on in parallel, each with its own copy of the model.
+#[derive(Debug)]
+pub struct ContextPool {
+    pub(crate) contexts: Vec<Mutex<WhisperContext>>,
+}
+
+/// How long one worker of [ContextPool::transcribe_parallel] took for its part of the audio.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkerTimings {
+    /// Index of the worker, and of its context in the pool.
+    pub worker: usize,
+    /// The part of the audio the worker transcribed.
+    pub audio: Range<Timestamp>,
+    /// Timings of the worker's [WhisperContext::full] call.
+    pub timings: Timings,
+}
+
+/// Result of [ContextPool::transcribe_parallel].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParallelTranscript {
+    /// Segments of all workers in time order.
+    ///
+    /// In its timings, `total` is the wall-clock time of the whole call,
+    /// while `mel`, `encode`, `decode` and `n_windows` are added up over the workers.
+    pub transcript: Transcript,
+    /// Timings of every worker, in the order of their audio.
+    pub workers: Vec<WorkerTimings>,
+}
+
+impl ParallelTranscript {
+    /// Time all workers spent, divided by the wall-clock time of the call.
+    /// Close to the number of workers if parallelism helped, close to 1 if the workers mostly waited on each other.
+    pub fn speedup(&self) -> f64 {
+        let busy: Duration = self.workers.iter().map(|w| w.timings.total).sum();
+        if self.transcript.timings.total.is_zero() {
+            0.0
+        } else {
+            busy.as_secs_f64() / self.transcript.timings.total.as_secs_f64()
+        }
+    }
+}
+
+impl ContextPool {
+    /// Create a pool from already loaded contexts.
+    ///
+    /// # Panics
+    /// Panics if `contexts` is empty.
+    pub fn new(contexts: Vec<WhisperContext>) -> Self {
+        assert!(
+            !contexts.is_empty(),
+            "a context pool needs at least one context"
+        );
+        Self {
+            contexts: contexts.into_iter().map(Mutex::new).collect(),
+        }
+    }
+
+    /// Load the model at `path` into `size` contexts, at least one.
+    ///
+    /// # Returns
+    /// Ok(Self) on success, Err(WhisperError) if any context fails to load.
+    pub fn from_path(path: &str, size: usize) -> Result<Self, WhisperError> {
+        let contexts = (0..size.max(1))
+            .map(|_| WhisperContext::new(path))
+            .collect::<Result<_, _>>()?;
+        Ok(Self::new(contexts))
+    }
+
+    /// Number of contexts in the pool.
+    pub fn len(&self) -> usize {
+        self.contexts.len()
+    }
+
+    /// Always false, a pool holds at least one context.
+    pub fn is_empty(&self) -> bool {
+        self.contexts.is_empty()
+    }
+
+    /// Get the contexts back.
+    pub fn into_inner(self) -> Vec<WhisperContext> {
+        self.contexts
+            .into_iter()
+            .map(|ctx| ctx.into_inner().unwrap_or_else(|e| e.into_inner()))
+            .collect()
+    }
+
+    /// Split the audio into one part per worker, and transcribe the parts at the same time
+    /// on separate contexts.
+    ///
+    /// A replacement for [WhisperContext::full_parallel]: parts are cut at the quietest point
+    /// near an even split, and a failing worker fails the whole call instead of leaving a gap.
+    /// Like there, accuracy can still be worse around the cuts, since no context carries over.
+    ///
+    /// # Arguments
+    /// * make_params: Creates the [FullParams] for every worker. Their offset and duration are ignored.
+    ///   Callbacks see the segments of a single part, with times relative to the start of the part.
+    /// * audio: 16 kHz mono PCM audio data.
+    /// * n_workers: Number of parts to split the audio into, limited to the size of the pool.
+    ///
+    /// # Returns
+    /// Ok([ParallelTranscript]) on success.
+    /// Err(WhisperError) of the first failed part otherwise, wrapped in a [WhisperError::Operation]
+    /// recording the worker and its part of the audio.
+    ///
+    /// # Panics
+    /// Panics if a worker panicked.
+    pub fn transcribe_parallel<'a, 'b, F>(
+        &self,
+        mut make_params: F,
+        audio: &[f32],
+        n_workers: usize,
+    ) -> Result<ParallelTranscript, WhisperError>
+    where
+        F: FnMut() -> FullParams<'a, 'b>,
+    {
+        let start = Instant::now();
+        let parts = split(audio, n_workers.clamp(1, self.contexts.len()));
+        let results: Vec<Result<(Vec<Segment>, Timings), WhisperError>> = thread::scope(|scope| {
+            let workers: Vec<_> = parts
+                .iter()
+                .zip(&self.contexts)
+                .map(|(part, ctx)| {
+                    let mut params = make_params();
+                    params.set_offset_ms(0);
+                    params.set_duration_ms(0);
+                    let audio = &audio[part.clone()];
+                    scope.spawn(move || {
+                        let mut ctx = ctx.lock().unwrap_or_else(|e| e.into_inner());
+                        let timings = ctx.full(params, audio)?;
+                        Ok((ctx.full_get_segments()?, timings))
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .map(|worker| match worker.join() {
+                    Ok(result) => result,
+                    Err(e) => std::panic::resume_unwind(e),
+                })
+                .collect()
+        });
+
+        let mut segments = Vec::new();
+        let mut timings = Timings {
+            audio: Duration::from_secs_f64(audio.len() as f64 / WHISPER_SAMPLE_RATE as f64),
+            ..Default::default()
+        };
+        let mut workers = Vec::with_capacity(parts.len());
+        for (worker, (part, result)) in parts.into_iter().zip(results).enumerate() {
+            let range = Timestamp::from_sample(part.start)..Timestamp::from_sample(part.end);
+            let (mut new, worker_timings) = result.map_err(|e| {
+                e.with_operation(
+                    Operation::TranscribeParallel {
+                        worker,
+                        start: range.start,
+                        end: range.end,
+                    },
+                    None,
+                )
+            })?;
+            new.iter_mut()
+                .for_each(|segment| shift(segment, range.start));
+            // parts don't overlap, this only keeps the segments in order
+            merge(&mut segments, new, range.start..range.start);
+
+            timings.load = timings.load.max(worker_timings.load);
+            timings.add_part(&worker_timings);
+            workers.push(WorkerTimings {
+                worker,
+                audio: range,
+                timings: worker_timings,
+            });
+        }
+        timings.total = start.elapsed();
+        Ok(ParallelTranscript {
+            transcript: Transcript { segments, timings },
+            workers,
+        })
+    }
+}
+
+/// Split audio into `n` parts, cutting at the quietest point near every even split.
+fn split(audio: &[f32], n: usize) -> Vec<Range<usize>> {
+    let part_len = audio.len() / n;
+    let search_len = (part_len / 4).min(MAX_SEARCH_LEN);
+    let mut parts = Vec::with_capacity(n);
+    let mut start = 0;
+    for i in 1..n {
+        let target = i * part_len;
+        let from = (target - search_len).max(start + FRAME_LEN);
+        let to = (target + search_len).min(audio.len());
+        if from >= to {
+            continue;
+        }
+        let end = quietest_point(audio, from..to);
+        parts.push(start..end);
+        start = end;
+    }
+    if start < audio.len() || parts.is_empty() {
+        parts.push(start..audio.len());
+    }
+    parts
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn split_at_quiet_points() {
+        let second = WHISPER_SAMPLE_RATE;
+        let mut audio = vec![0.5; 40 * second];
+        audio[11 * second..11 * second + FRAME_LEN].fill(0.0);
+        let parts = split(&audio, 4);
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts[0], 0..11 * second + FRAME_LEN / 2);
+        assert_eq!(parts[3].end, audio.len());
+        assert!(parts.windows(2).all(|w| w[0].end == w[1].start));
+
+        assert_eq!(split(&[], 3), vec![0..0]);
+        assert_eq!(split(&audio[..100], 3), vec![0..100]);
+    }
+}
@@ -0,0 +1,141 @@
+//! Progress of a running transcription.
+
+use crate::whisper_params::ProgressCallback;
+use std::time::{Duration, Instant};
+
+/// How far a [WhisperContext::full](crate::WhisperContext::full) call has come,
+/// passed to the callback set with [FullParams::set_progress_callback](crate::FullParams::set_progress_callback).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Progress {
+    /// Audio transcribed so far, counted from the start offset.
+    pub processed: Duration,
+    /// Audio to transcribe in total, taking the offset and duration of the params into account.
+    pub total: Duration,
+    /// Time since the call started.
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// Processed audio as a fraction of the total, between 0 and 1.
+    pub fn fraction(&self) -> f64 {
+        if self.total.is_zero() {
+            1.0
+        } else {
+            (self.processed.as_secs_f64() / self.total.as_secs_f64()).min(1.0)
+        }
+    }
+
+    /// Estimated time until the call finishes, assuming the rest of the audio
+    /// is transcribed at the speed it has been so far.
+    ///
+    /// # Returns
+    /// None until some audio was processed.
+    pub fn eta(&self) -> Option<Duration> {
+        let fraction = self.fraction();
+        if fraction <= 0.0 {
+            None
+        } else {
+            Some(self.elapsed.mul_f64((1.0 - fraction) / fraction))
+        }
+    }
+}
+
+/// Turns the positions reported by the callbacks of a call into [Progress] updates.
+pub(crate) struct ProgressTracker {
+    on_progress: ProgressCallback,
+    start: Instant,
+    /// Start offset into the audio.
+    offset: Duration,
+    total: Duration,
+    processed: Duration,
+}
+
+impl ProgressTracker {
+    /// # Arguments
+    /// * offset_ms, duration_ms: as set on the params, a duration of 0 meaning the rest of the audio.
+    /// * audio: duration of all audio passed in.
+    pub(crate) fn new(
+        on_progress: ProgressCallback,
+        offset_ms: i32,
+        duration_ms: i32,
+        audio: Duration,
+    ) -> Self {
+        let offset = Duration::from_millis(offset_ms.max(0) as u64).min(audio);
+        let mut total = audio - offset;
+        if duration_ms > 0 {
+            total = total.min(Duration::from_millis(duration_ms as u64));
+        }
+        Self {
+            on_progress,
+            start: Instant::now(),
+            offset,
+            total,
+            processed: Duration::ZERO,
+        }
+    }
+
+    /// Report the absolute position in the audio up to which everything was transcribed.
+    /// Positions never go backwards.
+    pub(crate) fn advance(&mut self, position: Duration) {
+        let processed = position.saturating_sub(self.offset).min(self.total);
+        self.processed = self.processed.max(processed);
+        self.report();
+    }
+
+    /// Report the whole audio as processed.
+    pub(crate) fn finish(&mut self) {
+        self.processed = self.total;
+        self.report();
+    }
+
+    /// Report the current progress again, as another window is about to be encoded.
+    pub(crate) fn report(&mut self) {
+        (self.on_progress)(Progress {
+            processed: self.processed,
+            total: self.total,
+            elapsed: self.start.elapsed(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn fraction_and_eta() {
+        let progress = Progress {
+            processed: Duration::from_secs(30),
+            total: Duration::from_secs(120),
+            elapsed: Duration::from_secs(10),
+        };
+        assert_eq!(progress.fraction(), 0.25);
+        assert_eq!(progress.eta(), Some(Duration::from_secs(30)));
+        let started = Progress {
+            processed: Duration::ZERO,
+            ..progress
+        };
+        assert_eq!(started.eta(), None);
+    }
+
+    #[test]
+    fn tracker_clamps_to_offset_and_duration() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&reports);
+        let mut tracker = ProgressTracker::new(
+            Box::new(move |p: Progress| sink.lock().unwrap().push(p.processed)),
+            10_000,
+            60_000,
+            Duration::from_secs(600),
+        );
+        tracker.advance(Duration::from_secs(40));
+        tracker.advance(Duration::from_secs(5));
+        tracker.advance(Duration::from_secs(200));
+        assert_eq!(tracker.total, Duration::from_secs(60));
+        assert_eq!(
+            *reports.lock().unwrap(),
+            [30, 30, 60].map(Duration::from_secs)
+        );
+    }
+}
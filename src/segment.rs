@@ -0,0 +1,274 @@
+//! Owned transcription results.
+
+use crate::timestamp::Timestamp;
+use crate::token_kind::TokenKind;
+use crate::{WhisperToken, WhisperTokenData};
+use std::ops::Range;
+
+/// A segment of transcribed text, copied out of the context.
+///
+/// Obtained with [WhisperContext::full_get_segment](crate::WhisperContext::full_get_segment)
+/// or [WhisperContext::full_get_segments](crate::WhisperContext::full_get_segments).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    /// Start time of the segment.
+    pub t0: Timestamp,
+    /// End time of the segment.
+    pub t1: Timestamp,
+    /// Text of the segment. Invalid UTF-8 is replaced with U+FFFD.
+    pub text: String,
+    /// Every token of the segment, including special and timestamp tokens.
+    pub tokens: Vec<SegmentToken>,
+}
+
+/// A single token of a [Segment].
+#[derive(Debug, Clone)]
+pub struct SegmentToken {
+    /// ID of the token.
+    pub id: WhisperToken,
+    /// What the token stands for.
+    pub kind: TokenKind,
+    /// Raw bytes of the token. May hold only part of a multi-byte UTF-8 character.
+    pub bytes: Vec<u8>,
+    /// Probabilities and timestamps of the token.
+    /// Timestamps are only set if [FullParams::set_token_timestamps](crate::FullParams::set_token_timestamps) was enabled.
+    pub data: WhisperTokenData,
+}
+
+// the bindgen generated token data doesn't implement PartialEq
+impl PartialEq for SegmentToken {
+    fn eq(&self, other: &Self) -> bool {
+        let (a, b) = (&self.data, &other.data);
+        self.id == other.id
+            && self.kind == other.kind
+            && self.bytes == other.bytes
+            && (a.id, a.tid, a.t0, a.t1) == (b.id, b.tid, b.t0, b.t1)
+            && (a.p, a.plog, a.pt, a.ptsum, a.vlen) == (b.p, b.plog, b.pt, b.ptsum, b.vlen)
+    }
+}
+
+/// A word assembled from one or more text tokens. See [Segment::words].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Word {
+    /// Text of the word, without surrounding whitespace.
+    pub text: String,
+    /// Start time of the first token of the word. See [SegmentToken::t0].
+    pub t0: Option<Timestamp>,
+    /// End time of the last token of the word. See [SegmentToken::t1].
+    pub t1: Option<Timestamp>,
+    /// Geometric mean of the probabilities of the tokens of the word.
+    pub probability: f32,
+    /// Lowest probability of any token of the word.
+    pub min_probability: f32,
+}
+
+impl Segment {
+    /// Rebuild the text from the tokens, after some were removed.
+    pub(crate) fn rebuild_text(&mut self) {
+        let bytes: Vec<u8> = self
+            .tokens
+            .iter()
+            .filter(|token| token.kind.is_text())
+            .flat_map(|token| token.bytes.iter().copied())
+            .collect();
+        self.text = String::from_utf8_lossy(&bytes).into_owned();
+    }
+
+    /// Remove the text tokens for which `keep` returns false, given their index and time,
+    /// and move the start and end of the segment to the text that is left.
+    pub(crate) fn retain_text(&mut self, mut keep: impl FnMut(usize, &Range<Timestamp>) -> bool) {
+        let times = self.token_times();
+        let kept: Vec<bool> = self
+            .tokens
+            .iter()
+            .enumerate()
+            .map(|(i, token)| !token.kind.is_text() || keep(i, &times[i]))
+            .collect();
+        let text: Vec<usize> = (0..self.tokens.len())
+            .filter(|&i| self.tokens[i].kind.is_text())
+            .collect();
+        let first = text.iter().find(|&&i| kept[i]);
+        let last = text.iter().rev().find(|&&i| kept[i]);
+        if let (Some(&first), Some(&last)) = (first, last) {
+            if text.first() != Some(&first) {
+                self.t0 = times[first].start;
+            }
+            if text.last() != Some(&last) {
+                self.t1 = times[last].end;
+            }
+        }
+        let mut i = 0;
+        self.tokens.retain(|_| {
+            i += 1;
+            kept[i - 1]
+        });
+        self.rebuild_text();
+    }
+
+    /// Start and end of every token, from the token timestamps if they were computed,
+    /// otherwise spread over the segment in proportion to the length of the text.
+    fn token_times(&self) -> Vec<Range<Timestamp>> {
+        let total: usize = self
+            .tokens
+            .iter()
+            .filter(|token| token.kind.is_text())
+            .map(|token| token.bytes.len())
+            .sum();
+        let duration = (self.t1 - self.t0).as_centis();
+        let at = |bytes: usize| {
+            self.t0 + Timestamp::from_centis(duration * bytes as i64 / total.max(1) as i64)
+        };
+        let mut offset = 0;
+        self.tokens
+            .iter()
+            .map(|token| {
+                let start = at(offset);
+                if token.kind.is_text() {
+                    offset += token.bytes.len();
+                }
+                match (token.t0(), token.t1()) {
+                    (Some(t0), Some(t1)) => t0..t1,
+                    _ => start..at(offset),
+                }
+            })
+            .collect()
+    }
+
+    /// Merge the text tokens of this segment into words.
+    ///
+    /// A new word starts at every token beginning with a space. Special and timestamp tokens are dropped.
+    /// Word timestamps require [FullParams::set_token_timestamps](crate::FullParams::set_token_timestamps),
+    /// otherwise they are None.
+    pub fn words(&self) -> Vec<Word> {
+        let mut words = Vec::new();
+        let mut pieces: Vec<&SegmentToken> = Vec::new();
+        for token in self.tokens.iter().filter(|t| t.kind.is_text()) {
+            if token.bytes.first() == Some(&b' ') && !pieces.is_empty() {
+                words.extend(Word::from_pieces(&pieces));
+                pieces.clear();
+            }
+            pieces.push(token);
+        }
+        words.extend(Word::from_pieces(&pieces));
+        words
+    }
+}
+
+impl SegmentToken {
+    /// Start time of the token.
+    ///
+    /// # Returns
+    /// None unless [FullParams::set_token_timestamps](crate::FullParams::set_token_timestamps) was enabled.
+    #[inline]
+    pub fn t0(&self) -> Option<Timestamp> {
+        (self.data.t0 >= 0).then_some(Timestamp::from_centis(self.data.t0))
+    }
+
+    /// End time of the token.
+    ///
+    /// # Returns
+    /// None unless [FullParams::set_token_timestamps](crate::FullParams::set_token_timestamps) was enabled.
+    #[inline]
+    pub fn t1(&self) -> Option<Timestamp> {
+        (self.data.t1 >= 0).then_some(Timestamp::from_centis(self.data.t1))
+    }
+}
+
+impl Word {
+    fn from_pieces(pieces: &[&SegmentToken]) -> Option<Self> {
+        let (first, last) = (pieces.first()?, pieces.last()?);
+        let bytes: Vec<u8> = pieces
+            .iter()
+            .flat_map(|t| t.bytes.iter().copied())
+            .collect();
+        let text = String::from_utf8_lossy(&bytes).trim().to_string();
+        if text.is_empty() {
+            return None;
+        }
+        let log_sum: f32 = pieces.iter().map(|t| t.data.p.ln()).sum();
+        Some(Self {
+            text,
+            t0: first.t0(),
+            t1: last.t1(),
+            probability: (log_sum / pieces.len() as f32).exp(),
+            min_probability: pieces
+                .iter()
+                .map(|t| t.data.p)
+                .fold(f32::INFINITY, f32::min),
+        })
+    }
+}
+
+#[cfg(test)]
+impl SegmentToken {
+    /// A token for tests, with probability `p` and token timestamps `t0` and `t1` in centiseconds,
+    /// -1 if not set.
+    pub(crate) fn for_test(
+        id: WhisperToken,
+        kind: TokenKind,
+        bytes: &[u8],
+        p: f32,
+        t0: i64,
+        t1: i64,
+    ) -> Self {
+        Self {
+            id,
+            kind,
+            bytes: bytes.to_vec(),
+            data: WhisperTokenData {
+                id,
+                tid: 0,
+                p,
+                plog: p.ln(),
+                pt: 0.0,
+                ptsum: 0.0,
+                t0,
+                t1,
+                vlen: 0.0,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn token(text: &[u8], kind: TokenKind, p: f32, t0: i64, t1: i64) -> SegmentToken {
+        SegmentToken::for_test(0, kind, text, p, t0, t1)
+    }
+
+    #[test]
+    fn words_from_pieces() {
+        let segment = Segment {
+            t0: Timestamp::from_centis(0),
+            t1: Timestamp::from_centis(100),
+            text: " Hello, wörld".to_string(),
+            tokens: vec![
+                token(
+                    b"[_BEG_]",
+                    TokenKind::Timestamp(Default::default()),
+                    1.0,
+                    0,
+                    0,
+                ),
+                token(b" Hel", TokenKind::Text, 0.5, 0, 10),
+                token(b"lo,", TokenKind::Text, 0.5, 10, 20),
+                // "ö" split across two tokens
+                token(b" w\xc3", TokenKind::Text, 1.0, 30, 40),
+                token(b"\xb6rld", TokenKind::Text, 0.25, 40, 50),
+                token(b"[_EOT_]", TokenKind::Eot, 1.0, 50, 50),
+            ],
+        };
+        let words = segment.words();
+        assert_eq!(words.len(), 2);
+        assert_eq!(words[0].text, "Hello,");
+        let t = |centis| Some(Timestamp::from_centis(centis));
+        assert_eq!((words[0].t0, words[0].t1), (t(0), t(20)));
+        assert!((words[0].probability - 0.5).abs() < 1e-6);
+        assert_eq!(words[1].text, "wörld");
+        assert_eq!((words[1].t0, words[1].t1), (t(30), t(50)));
+        assert!((words[1].probability - 0.5).abs() < 1e-6);
+        assert_eq!(words[1].min_probability, 0.25);
+    }
+}
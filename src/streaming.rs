@@ -0,0 +1,79 @@
+//! Receive segments from a background transcription while it is still decoding.
+
+use crate::error::WhisperError;
+use crate::segment::Segment;
+use crate::timings::Timings;
+use crate::whisper_ctx::WhisperContext;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// A [WhisperContext::full] call running on a background thread,
+/// started by [WhisperContext::full_streaming].
+///
+/// Iterating yields every segment as soon as it is decoded, and ends when the transcription does.
+/// Call [join](StreamingTranscription::join) afterwards to get the context back and see whether it succeeded.
+#[derive(Debug)]
+pub struct StreamingTranscription {
+    segments: Receiver<Segment>,
+    abort: Arc<AtomicBool>,
+    handle: JoinHandle<(WhisperContext, Result<Timings, WhisperError>)>,
+}
+
+impl StreamingTranscription {
+    pub(crate) fn spawn<F>(ctx: WhisperContext, abort: Arc<AtomicBool>, run: F) -> Self
+    where
+        F: FnOnce(&mut WhisperContext, mpsc::Sender<Segment>) -> Result<Timings, WhisperError>
+            + Send
+            + 'static,
+    {
+        let (sender, segments) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let mut ctx = ctx;
+            let result = run(&mut ctx, sender);
+            (ctx, result)
+        });
+        Self {
+            segments,
+            abort,
+            handle,
+        }
+    }
+
+    /// The channel new segments are sent on. It disconnects when the transcription ends.
+    pub fn receiver(&self) -> &Receiver<Segment> {
+        &self.segments
+    }
+
+    /// Stop the transcription before the encoder runs on the next 30 second window.
+    /// [join](StreamingTranscription::join) then returns [WhisperError::Aborted].
+    pub fn abort(&self) {
+        self.abort.store(true, Ordering::SeqCst);
+    }
+
+    /// Wait for the transcription to end.
+    ///
+    /// Segments that weren't received yet are dropped.
+    ///
+    /// # Returns
+    /// The context, with all segments still available through [WhisperContext::full_get_segments],
+    /// and the result of [WhisperContext::full].
+    ///
+    /// # Panics
+    /// Panics if the transcription panicked.
+    pub fn join(self) -> (WhisperContext, Result<Timings, WhisperError>) {
+        match self.handle.join() {
+            Ok(result) => result,
+            Err(e) => std::panic::resume_unwind(e),
+        }
+    }
+}
+
+impl Iterator for StreamingTranscription {
+    type Item = Segment;
+
+    fn next(&mut self) -> Option<Segment> {
+        self.segments.recv().ok()
+    }
+}
@@ -0,0 +1,153 @@
+//! Timestamps within the input audio.
+
+use std::fmt;
+use std::ops::{Add, Sub};
+use std::time::Duration;
+
+/// Sample rate of the audio whisper.cpp expects, in Hz.
+pub const WHISPER_SAMPLE_RATE: usize = 16000;
+
+/// Number of samples in one unit of whisper.cpp time (10 ms).
+const SAMPLES_PER_CENTI: usize = WHISPER_SAMPLE_RATE / 100;
+
+/// A point in time within the input audio.
+///
+/// whisper.cpp reports times in units of 10 ms. Timestamps of segments and tokens are absolute:
+/// they are relative to the start of the samples passed to [WhisperContext::full](crate::WhisperContext::full),
+/// and already include any offset set with [FullParams::set_offset_ms](crate::FullParams::set_offset_ms).
+///
+/// Displays as `HH:MM:SS.mmm`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(i64);
+
+impl Timestamp {
+    /// The start of the audio.
+    pub const ZERO: Self = Self(0);
+
+    /// Create a timestamp from whisper.cpp's native unit of 10 ms.
+    #[inline]
+    pub const fn from_centis(centis: i64) -> Self {
+        Self(centis)
+    }
+
+    /// Create a timestamp from milliseconds, rounding down to a multiple of 10 ms.
+    #[inline]
+    pub const fn from_millis(millis: i64) -> Self {
+        Self(millis.div_euclid(10))
+    }
+
+    /// Create a timestamp from a sample index at 16 kHz, rounding down to a multiple of 10 ms.
+    #[inline]
+    pub const fn from_sample(sample: usize) -> Self {
+        Self((sample / SAMPLES_PER_CENTI) as i64)
+    }
+
+    /// The timestamp in whisper.cpp's native unit of 10 ms.
+    #[inline]
+    pub const fn as_centis(self) -> i64 {
+        self.0
+    }
+
+    /// The timestamp in milliseconds.
+    #[inline]
+    pub const fn as_millis(self) -> i64 {
+        self.0 * 10
+    }
+
+    /// The timestamp in seconds.
+    #[inline]
+    pub fn as_secs_f64(self) -> f64 {
+        self.0 as f64 / 100.0
+    }
+
+    /// The timestamp in seconds.
+    #[inline]
+    pub fn as_secs_f32(self) -> f32 {
+        self.0 as f32 / 100.0
+    }
+
+    /// The index of the first sample at this time, at 16 kHz. Negative timestamps map to 0.
+    #[inline]
+    pub const fn to_sample(self) -> usize {
+        if self.0 < 0 {
+            0
+        } else {
+            self.0 as usize * SAMPLES_PER_CENTI
+        }
+    }
+
+    /// The timestamp as a [Duration]. Negative timestamps map to [Duration::ZERO].
+    #[inline]
+    pub fn as_duration(self) -> Duration {
+        Duration::from_millis(self.as_millis().max(0) as u64)
+    }
+}
+
+impl From<Timestamp> for Duration {
+    #[inline]
+    fn from(timestamp: Timestamp) -> Self {
+        timestamp.as_duration()
+    }
+}
+
+impl From<Duration> for Timestamp {
+    /// Rounds down to a multiple of 10 ms.
+    #[inline]
+    fn from(duration: Duration) -> Self {
+        Self((duration.as_millis() / 10) as i64)
+    }
+}
+
+impl Add for Timestamp {
+    type Output = Self;
+
+    #[inline]
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Timestamp {
+    type Output = Self;
+
+    #[inline]
+    fn sub(self, rhs: Self) -> Self {
+        Self(self.0 - rhs.0)
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let millis = self.as_millis();
+        let sign = if millis < 0 { "-" } else { "" };
+        let millis = millis.unsigned_abs();
+        write!(
+            f,
+            "{}{:02}:{:02}:{:02}.{:03}",
+            sign,
+            millis / 3_600_000,
+            millis / 60_000 % 60,
+            millis / 1000 % 60,
+            millis % 1000
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        let t = Timestamp::from_centis(6150);
+        assert_eq!(t.as_millis(), 61_500);
+        assert_eq!(t.as_secs_f64(), 61.5);
+        assert_eq!(t.to_sample(), 984_000);
+        assert_eq!(Timestamp::from_sample(984_000), t);
+        assert_eq!(Timestamp::from_millis(61_509), t);
+        assert_eq!(Duration::from(t), Duration::from_millis(61_500));
+        assert_eq!(Timestamp::from(Duration::from_millis(61_500)), t);
+        assert_eq!(t.to_string(), "00:01:01.500");
+        assert_eq!(Timestamp::from_centis(-1).as_duration(), Duration::ZERO);
+    }
+}
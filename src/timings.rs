@@ -0,0 +1,127 @@
+//! Wall-clock timings of a transcription.
+
+use crate::capture::{self, CapturedLine};
+use std::time::Duration;
+
+/// Wall-clock timings of a single [WhisperContext::full](crate::WhisperContext::full) call.
+///
+/// `encode` and `decode` are the timings whisper.cpp keeps itself, which are reset at the start of every call,
+/// so [WhisperContext::print_timings](crate::WhisperContext::print_timings) only covers the last call.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Timings {
+    /// Time it took to load the model when the context was created.
+    pub load: Duration,
+    /// Time from the start of the call until the encoder first ran, mostly computing the spectrogram.
+    pub mel: Duration,
+    /// Time spent running the encoder over all windows.
+    ///
+    /// whisper.cpp only prints this, so it is read back from its output, which is only possible on Unix.
+    /// None on other platforms.
+    pub encode: Option<Duration>,
+    /// Time spent running the decoder over all windows, excluding sampling.
+    ///
+    /// None on platforms other than Unix, like `encode`.
+    pub decode: Option<Duration>,
+    /// Total time of the call, excluding `load`.
+    pub total: Duration,
+    /// Duration of the audio passed in.
+    pub audio: Duration,
+    /// Number of 30 second windows the encoder ran on.
+    pub n_windows: usize,
+}
+
+impl Timings {
+    /// Processing time divided by audio duration. Below 1 means faster than real time.
+    ///
+    /// # Returns
+    /// 0 if no audio was passed in.
+    pub fn real_time_factor(&self) -> f64 {
+        if self.audio.is_zero() {
+            0.0
+        } else {
+            self.total.as_secs_f64() / self.audio.as_secs_f64()
+        }
+    }
+
+    /// Add the processing times of another call on part of the audio. `encode` and `decode`
+    /// are added up over the calls that reported them.
+    pub(crate) fn add_part(&mut self, part: &Timings) {
+        let add = |a: Option<Duration>, b: Option<Duration>| match (a, b) {
+            (Some(a), Some(b)) => Some(a + b),
+            (a, b) => a.or(b),
+        };
+        self.mel += part.mel;
+        self.encode = add(self.encode, part.encode);
+        self.decode = add(self.decode, part.decode);
+        self.n_windows += part.n_windows;
+    }
+}
+
+/// Read the encoder and decoder time whisper.cpp measured since the last `whisper_reset_timings`.
+///
+/// whisper.cpp only prints them, so they are printed into a capture and read back,
+/// without reaching the sink of an installed [OutputCapture](crate::OutputCapture) or stderr.
+///
+/// # Returns
+/// (encode, decode), or None if stderr can't be captured or they couldn't be parsed.
+pub(crate) fn read_encode_decode(
+    ctx: *mut whisper_rs_sys::whisper_context,
+) -> Option<(Duration, Duration)> {
+    let lines = capture::quiet("whisper_print_timings", || unsafe {
+        whisper_rs_sys::whisper_print_timings(ctx)
+    })?;
+    Some((parse_time(&lines, "encode")?, parse_time(&lines, "decode")?))
+}
+
+/// Parse `whisper_print_timings:   encode time =   123.45 ms /     2 runs (   61.72 ms per run)`.
+fn parse_time(lines: &[CapturedLine], name: &str) -> Option<Duration> {
+    let pattern = format!("{} time =", name);
+    lines.iter().rev().find_map(|line| {
+        let (_, rest) = line.text.split_once(&pattern)?;
+        let millis: f64 = rest.split_whitespace().next()?.parse().ok()?;
+        Duration::try_from_secs_f64(millis / 1000.0).ok()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn real_time_factor() {
+        let timings = Timings {
+            total: Duration::from_secs(3),
+            audio: Duration::from_secs(12),
+            ..Default::default()
+        };
+        assert_eq!(timings.real_time_factor(), 0.25);
+        assert_eq!(Timings::default().real_time_factor(), 0.0);
+    }
+
+    #[test]
+    fn parse_print_timings() {
+        let lines: Vec<CapturedLine> = [
+            "whisper_print_timings:     load time =   312.51 ms",
+            "whisper_print_timings:      mel time =    21.03 ms",
+            "whisper_print_timings:   sample time =     5.20 ms /    31 runs (    0.17 ms per run)",
+            "whisper_print_timings:   encode time =  1523.76 ms /     1 runs ( 1523.76 ms per run)",
+            "whisper_print_timings:   decode time =   120.50 ms /    31 runs (    3.89 ms per run)",
+            "whisper_print_timings:    total time =  2001.17 ms",
+        ]
+        .iter()
+        .map(|text| CapturedLine {
+            level: capture::CaptureLevel::parse(text),
+            text: text.to_string(),
+        })
+        .collect();
+        assert_eq!(
+            parse_time(&lines, "encode"),
+            Some(Duration::from_micros(1_523_760))
+        );
+        assert_eq!(
+            parse_time(&lines, "decode"),
+            Some(Duration::from_micros(120_500))
+        );
+        assert_eq!(parse_time(&lines[..2], "encode"), None);
+    }
+}
@@ -0,0 +1,157 @@
+//! Classification of token IDs.
+
+use crate::language::{Language, Task};
+use crate::WhisperToken;
+use std::time::Duration;
+
+/// Duration of a single step between two timestamp tokens.
+pub(crate) const TIMESTAMP_STEP: Duration = Duration::from_millis(20);
+
+/// What a token ID stands for. Obtained with [WhisperContext::classify](crate::WhisperContext::classify).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    /// A regular text token.
+    Text,
+    /// End of transcript.
+    Eot,
+    /// Start of transcript.
+    Sot,
+    /// Start of the previous text used as prompt.
+    Prev,
+    /// Start of language model prompt. Also used for the token whisper.cpp calls `solm`,
+    /// which Whisper itself uses for "no speech".
+    Solm,
+    /// The model should not predict timestamps.
+    NoTimestamps,
+    /// Language token.
+    Language(Language),
+    /// Task token.
+    Task(Task),
+    /// Timestamp token, holding the time relative to the start of the current 30 second window.
+    Timestamp(Duration),
+}
+
+impl TokenKind {
+    /// Is this a regular text token?
+    #[inline]
+    pub fn is_text(&self) -> bool {
+        matches!(self, Self::Text)
+    }
+
+    /// Is this a timestamp token?
+    #[inline]
+    pub fn is_timestamp(&self) -> bool {
+        matches!(self, Self::Timestamp(_))
+    }
+}
+
+/// IDs of the special tokens of a model's vocabulary.
+///
+/// English-only models number their special tokens one lower than multilingual ones,
+/// but both lay them out the same way: eot, sot, one token per language, translate, transcribe,
+/// start of LM, prev, no speech, no timestamps, and the timestamps from beg on.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct SpecialTokens {
+    pub(crate) n_vocab: WhisperToken,
+    pub(crate) eot: WhisperToken,
+    pub(crate) sot: WhisperToken,
+    pub(crate) prev: WhisperToken,
+    pub(crate) not: WhisperToken,
+    pub(crate) beg: WhisperToken,
+}
+
+impl SpecialTokens {
+    pub(crate) fn translate(&self) -> WhisperToken {
+        self.prev - 3
+    }
+
+    pub(crate) fn transcribe(&self) -> WhisperToken {
+        self.prev - 2
+    }
+
+    /// Classify a token ID, or None if it is outside the vocabulary.
+    pub(crate) fn classify(&self, token: WhisperToken) -> Option<TokenKind> {
+        if token < 0 || token >= self.n_vocab {
+            return None;
+        }
+        Some(if token < self.eot {
+            TokenKind::Text
+        } else if token == self.eot {
+            TokenKind::Eot
+        } else if token == self.sot {
+            TokenKind::Sot
+        } else if token >= self.beg {
+            TokenKind::Timestamp(TIMESTAMP_STEP * (token - self.beg) as u32)
+        } else if token < self.translate() {
+            TokenKind::Language(Language::from_id_unchecked(token - self.sot - 1))
+        } else if token == self.translate() {
+            TokenKind::Task(Task::Translate)
+        } else if token == self.transcribe() {
+            TokenKind::Task(Task::Transcribe)
+        } else if token == self.prev {
+            TokenKind::Prev
+        } else if token == self.not {
+            TokenKind::NoTimestamps
+        } else {
+            // start of LM before prev, and no speech after it
+            TokenKind::Solm
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn classify_english_and_multilingual() {
+        let english = SpecialTokens {
+            n_vocab: 51864,
+            eot: 50256,
+            sot: 50257,
+            prev: 50360,
+            not: 50362,
+            beg: 50363,
+        };
+        let multilingual = SpecialTokens {
+            n_vocab: 51865,
+            eot: 50257,
+            sot: 50258,
+            prev: 50361,
+            not: 50363,
+            beg: 50364,
+        };
+        for (tokens, offset) in [(english, 0), (multilingual, 1)] {
+            let kind = |token: WhisperToken| tokens.classify(token + offset);
+            assert_eq!(kind(0), Some(TokenKind::Text));
+            assert_eq!(kind(50255), Some(TokenKind::Text));
+            assert_eq!(kind(50256), Some(TokenKind::Eot));
+            assert_eq!(kind(50257), Some(TokenKind::Sot));
+            assert_eq!(
+                kind(50258),
+                Some(TokenKind::Language(Language::from_id_unchecked(0)))
+            );
+            assert_eq!(
+                kind(50356),
+                Some(TokenKind::Language(Language::from_id_unchecked(98)))
+            );
+            assert_eq!(kind(50357), Some(TokenKind::Task(Task::Translate)));
+            assert_eq!(kind(50358), Some(TokenKind::Task(Task::Transcribe)));
+            assert_eq!(kind(50359), Some(TokenKind::Solm));
+            assert_eq!(kind(50360), Some(TokenKind::Prev));
+            assert_eq!(kind(50361), Some(TokenKind::Solm));
+            assert_eq!(kind(50362), Some(TokenKind::NoTimestamps));
+            assert_eq!(kind(50363), Some(TokenKind::Timestamp(Duration::ZERO)));
+            assert_eq!(
+                kind(50363 + 1500),
+                Some(TokenKind::Timestamp(Duration::from_secs(30)))
+            );
+        }
+        assert_eq!(english.classify(-1), None);
+        assert_eq!(english.classify(51864), None);
+        assert_eq!(
+            multilingual.classify(51864),
+            Some(TokenKind::Timestamp(Duration::from_secs(30)))
+        );
+    }
+}
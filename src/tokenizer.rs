@@ -0,0 +1,147 @@
+//! Byte-level access to the model vocabulary.
+
+use crate::error::WhisperError;
+use crate::whisper_ctx::WhisperContext;
+use crate::WhisperToken;
+use std::cell::OnceCell;
+use std::collections::HashMap;
+
+/// Byte-level view of the vocabulary of a [WhisperContext].
+///
+/// Whisper uses byte-level BPE, so a single token may hold only part of a multi-byte UTF-8
+/// character (this happens constantly with CJK, emoji, and accented text).
+/// Converting tokens to strings one at a time will therefore fail or produce garbage,
+/// while [Tokenizer::decode] concatenates the bytes of all tokens first.
+///
+/// Obtained with [WhisperContext::tokenizer].
+#[derive(Debug)]
+pub struct Tokenizer<'a> {
+    ctx: &'a WhisperContext,
+    /// built the first time [Tokenizer::token_id] is called
+    lookup: OnceCell<HashMap<&'a [u8], WhisperToken>>,
+}
+
+impl<'a> Tokenizer<'a> {
+    pub(crate) fn new(ctx: &'a WhisperContext) -> Self {
+        Self {
+            ctx,
+            lookup: OnceCell::new(),
+        }
+    }
+
+    /// Size of the vocabulary.
+    #[inline]
+    pub fn n_vocab(&self) -> usize {
+        self.ctx.n_vocab() as usize
+    }
+
+    /// Get the raw bytes of a token.
+    ///
+    /// # Returns
+    /// None if the token ID is out of range.
+    pub fn token_bytes(&self, token: WhisperToken) -> Option<&'a [u8]> {
+        if token < 0 || token as usize >= self.n_vocab() {
+            return None;
+        }
+        self.ctx.token_to_bytes(token).ok()
+    }
+
+    /// Is this a special token (eot, sot, language, task, timestamp, etc.) rather than text?
+    #[inline]
+    pub fn is_special(&self, token: WhisperToken) -> bool {
+        token >= self.ctx.token_eot()
+    }
+
+    /// Look up the ID of the token with exactly the given bytes.
+    ///
+    /// The first call builds a lookup table over the entire vocabulary.
+    pub fn token_id(&self, bytes: impl AsRef<[u8]>) -> Option<WhisperToken> {
+        self.lookup
+            .get_or_init(|| self.iter().map(|(id, bytes)| (bytes, id)).collect())
+            .get(bytes.as_ref())
+            .copied()
+    }
+
+    /// Iterate over the whole vocabulary as pairs of token ID and token bytes.
+    pub fn iter(&self) -> impl Iterator<Item = (WhisperToken, &'a [u8])> + '_ {
+        (0..self.n_vocab() as WhisperToken)
+            .filter_map(|id| self.token_bytes(id).map(|bytes| (id, bytes)))
+    }
+
+    /// Convert text into tokens. See [WhisperContext::tokenize].
+    ///
+    /// Decoding the result with [Tokenizer::decode] returns the original text.
+    #[inline]
+    pub fn tokenize(&self, text: &str) -> Result<Vec<WhisperToken>, WhisperError> {
+        self.ctx.tokenize(text)
+    }
+
+    /// Concatenate the bytes of the text tokens in `tokens`.
+    ///
+    /// Special tokens and unknown token IDs are skipped.
+    pub fn decode_bytes(&self, tokens: &[WhisperToken]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for &token in tokens {
+            if self.is_special(token) {
+                continue;
+            }
+            if let Some(b) = self.token_bytes(token) {
+                bytes.extend_from_slice(b);
+            }
+        }
+        bytes
+    }
+
+    /// Decode a sequence of tokens into a string.
+    ///
+    /// Special tokens and unknown token IDs are skipped.
+    ///
+    /// # Returns
+    /// Ok(String) on success, Err(WhisperError::InvalidUtf8) if the concatenated bytes are not valid UTF-8,
+    /// for example because the sequence ends in the middle of a character.
+    pub fn decode(&self, tokens: &[WhisperToken]) -> Result<String, WhisperError> {
+        String::from_utf8(self.decode_bytes(tokens)).map_err(|e| e.utf8_error().into())
+    }
+
+    /// Decode a sequence of tokens into a string, replacing invalid UTF-8 with U+FFFD.
+    ///
+    /// Special tokens and unknown token IDs are skipped.
+    pub fn decode_lossy(&self, tokens: &[WhisperToken]) -> String {
+        String::from_utf8_lossy(&self.decode_bytes(tokens)).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Needs a model: `WHISPER_TEST_MODEL=path/to/ggml-model.bin cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn round_trip() {
+        let path = std::env::var("WHISPER_TEST_MODEL").expect("WHISPER_TEST_MODEL is not set");
+        let ctx = WhisperContext::new(&path).unwrap();
+        let tokenizer = ctx.tokenizer();
+        for text in [
+            " Hello, world!",
+            " Café crème brûlée",
+            " 日本語のテキスト",
+            " 🎉👍",
+        ] {
+            let tokens = tokenizer.tokenize(text).unwrap();
+            assert_eq!(tokenizer.decode(&tokens).unwrap(), text);
+            for &token in &tokens {
+                let bytes = tokenizer.token_bytes(token).unwrap();
+                assert_eq!(tokenizer.token_id(bytes), Some(token));
+            }
+        }
+
+        // byte-level tokens split characters, which only decode once complete
+        let tokens = tokenizer.tokenize(" 🎉").unwrap();
+        assert!(tokens.len() > 1);
+        assert!(tokenizer.decode(&tokens[..tokens.len() - 1]).is_err());
+        assert!(tokenizer
+            .decode_lossy(&tokens[..tokens.len() - 1])
+            .ends_with('\u{fffd}'));
+    }
+}
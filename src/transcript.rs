@@ -0,0 +1,20 @@
+//! The complete result of a transcription.
+
+use crate::segment::Segment;
+use crate::timings::Timings;
+
+/// Every segment of a finished transcription, and how long it took.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transcript {
+    /// The transcribed segments, in order.
+    pub segments: Vec<Segment>,
+    /// Wall-clock timings of the transcription.
+    pub timings: Timings,
+}
+
+impl Transcript {
+    /// The text of all segments, joined together.
+    pub fn text(&self) -> String {
+        self.segments.iter().map(|s| s.text.as_str()).collect()
+    }
+}
@@ -18,6 +18,35 @@ pub fn convert_integer_to_float_audio(samples: &[i16]) -> Vec<f32> {
     floats
 }
 
+/// Convert raw 16 bit signed little endian mono PCM to a vector of 32 bit floats.
+///
+/// # Arguments
+/// * `bytes` - The raw PCM data. A trailing odd byte is ignored.
+///
+/// # Returns
+/// A vector of 32 bit floats.
+pub fn convert_s16le_to_float_audio(bytes: &[u8]) -> Vec<f32> {
+    let samples: Vec<i16> = bytes
+        .chunks_exact(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+        .collect();
+    convert_integer_to_float_audio(&samples)
+}
+
+/// Convert raw 32 bit float little endian mono PCM to a vector of 32 bit floats.
+///
+/// # Arguments
+/// * `bytes` - The raw PCM data. Trailing bytes that don't make up a whole sample are ignored.
+///
+/// # Returns
+/// A vector of 32 bit floats.
+pub fn convert_f32le_to_float_audio(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
 /// Convert an array of 16 bit mono audio samples to a vector of 32 bit floats.
 ///
 /// This variant uses SIMD instructions, and as such is only available on
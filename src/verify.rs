@@ -0,0 +1,492 @@
+//! Integrity checks of ggml model files.
+
+use crate::model_info::ModelInfo;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+/// SHA-1 hashes of the official ggml models, as listed in whisper.cpp's `models/README.md`.
+pub const OFFICIAL_MODEL_SHA1: &[(&str, &str)] = &[
+    ("tiny", "bd577a113a864445d4c299885e0cb97d4ba92b5f"),
+    ("tiny.en", "c78c86eb1a8faa21b369bcd33207cc90d64ae9df"),
+    ("base", "465707469ff3a37a2b9b8d8f89f2f99de7299dac"),
+    ("base.en", "137c40403d78fd54d454da0f9bd998f78703390c"),
+    ("small", "55356645c2b361a969dfd0ef2c5a50d530afd8d5"),
+    ("small.en", "db8a495a91d927739e50b3fc1cc4c6b8f6c2d022"),
+    ("medium", "fd9727b6e1217c2f614f9b698455c4ffd82463b4"),
+    ("medium.en", "8c30f0e44ce9560643ebd10bbe50cd20eafd3723"),
+    ("large-v1", "b1caaf735c4cc1429223d5a74f0f4d0b9b59a299"),
+    ("large", "0f4c8e34f21cf1a914c59d8b3ce882345ad349d6"),
+];
+
+/// Longest tensor name accepted. The longest in a Whisper model is well below this.
+const MAX_TENSOR_NAME_LEN: usize = 256;
+
+/// What is wrong with a model file, found by [verify_model].
+#[derive(Debug)]
+pub enum ModelError {
+    /// Reading the file failed.
+    Io(io::Error),
+    /// The header is not that of a ggml Whisper model.
+    InvalidHeader(String),
+    /// The file ends before `section` is complete.
+    Truncated {
+        /// The part of the file being read.
+        section: String,
+        /// Offset the section would end at.
+        expected: u64,
+        /// Size of the file.
+        file_size: u64,
+    },
+    /// A tensor header holds impossible values.
+    InvalidTensor {
+        /// Offset of the tensor header in the file.
+        offset: u64,
+        /// What is wrong with it.
+        reason: String,
+    },
+    /// The file holds a tensor the model doesn't have.
+    UnexpectedTensor(String),
+    /// The file holds the same tensor twice.
+    DuplicateTensor(String),
+    /// A tensor has a different shape than the hyperparameters imply.
+    ShapeMismatch {
+        /// Name of the tensor.
+        name: String,
+        /// Shape implied by the hyperparameters, innermost dimension first.
+        expected: Vec<i64>,
+        /// Shape in the file.
+        actual: Vec<i64>,
+    },
+    /// Tensors of the model that are not in the file.
+    MissingTensors(Vec<String>),
+    /// The hash of the file doesn't match.
+    #[cfg(feature = "checksum")]
+    ChecksumMismatch {
+        /// The expected hash, or "an official model" when compared against [OFFICIAL_MODEL_SHA1].
+        expected: String,
+        /// The hash of the file, in lowercase hex.
+        actual: String,
+    },
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "failed to read the model: {}", e),
+            Self::InvalidHeader(reason) => write!(f, "invalid model header: {}", reason),
+            Self::Truncated {
+                section,
+                expected,
+                file_size,
+            } => write!(
+                f,
+                "the model is truncated: {} ends at byte {}, but the file has {} bytes",
+                section, expected, file_size
+            ),
+            Self::InvalidTensor { offset, reason } => {
+                write!(f, "invalid tensor header at byte {}: {}", offset, reason)
+            }
+            Self::UnexpectedTensor(name) => write!(f, "unexpected tensor {:?}", name),
+            Self::DuplicateTensor(name) => write!(f, "tensor {:?} appears twice", name),
+            Self::ShapeMismatch {
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "tensor {:?} has shape {:?}, expected {:?}",
+                name, actual, expected
+            ),
+            Self::MissingTensors(names) => {
+                write!(
+                    f,
+                    "{} tensors are missing: {}",
+                    names.len(),
+                    names.join(", ")
+                )
+            }
+            #[cfg(feature = "checksum")]
+            Self::ChecksumMismatch { expected, actual } => {
+                write!(f, "the model hash is {}, expected {}", actual, expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ModelError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ModelError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Reads sections of a model file, keeping track of the offset to report truncation precisely.
+struct ModelReader {
+    reader: BufReader<File>,
+    offset: u64,
+    file_size: u64,
+}
+
+impl ModelReader {
+    /// Fail unless `len` more bytes of `section` are in the file.
+    fn require(&self, len: u64, section: impl FnOnce() -> String) -> Result<(), ModelError> {
+        let expected = self.offset.saturating_add(len);
+        if expected > self.file_size {
+            Err(ModelError::Truncated {
+                section: section(),
+                expected,
+                file_size: self.file_size,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    fn read_i32(&mut self, section: &str) -> Result<i32, ModelError> {
+        self.require(4, || section.to_string())?;
+        let mut bytes = [0; 4];
+        self.reader.read_exact(&mut bytes)?;
+        self.offset += 4;
+        Ok(i32::from_le_bytes(bytes))
+    }
+
+    fn skip(&mut self, len: u64, section: impl FnOnce() -> String) -> Result<(), ModelError> {
+        self.require(len, section)?;
+        self.reader.seek_relative(len as i64)?;
+        self.offset += len;
+        Ok(())
+    }
+}
+
+/// Bytes taken by `n` elements of a ggml tensor type, or None for unknown types and incomplete blocks.
+fn tensor_size(ttype: i32, n: u64, quantization_version: i32) -> Option<u64> {
+    let (block_len, block_size) = match ttype {
+        0 => (1, 4),
+        1 => (1, 2),
+        // the scales of the first quantization format were 32 bit floats
+        2 => (32, if quantization_version == 0 { 20 } else { 18 }),
+        3 => (32, if quantization_version == 0 { 24 } else { 20 }),
+        6 => (32, 22),
+        7 => (32, 24),
+        8 => (32, if quantization_version < 2 { 36 } else { 34 }),
+        _ => return None,
+    };
+    n.is_multiple_of(block_len)
+        .then_some(n / block_len * block_size)
+}
+
+/// Names and shapes of the tensors of a model with these hyperparameters, innermost dimension first.
+fn expected_tensors(info: &ModelInfo) -> HashMap<String, Vec<i64>> {
+    let mut tensors = HashMap::new();
+    let mut add = |name: String, shape: &[i32]| {
+        tensors.insert(name, shape.iter().map(|&dim| dim as i64).collect());
+    };
+
+    let s = info.n_audio_state;
+    add(
+        "encoder.positional_embedding".into(),
+        &[s, info.n_audio_ctx],
+    );
+    add("encoder.conv1.weight".into(), &[3, info.n_mels, s]);
+    add("encoder.conv1.bias".into(), &[1, s]);
+    add("encoder.conv2.weight".into(), &[3, s, s]);
+    add("encoder.conv2.bias".into(), &[1, s]);
+    add("encoder.ln_post.weight".into(), &[s]);
+    add("encoder.ln_post.bias".into(), &[s]);
+    for i in 0..info.n_audio_layer {
+        let block = format!("encoder.blocks.{}", i);
+        add_block(&mut add, &block, s, false);
+    }
+
+    let s = info.n_text_state;
+    add("decoder.positional_embedding".into(), &[s, info.n_text_ctx]);
+    add("decoder.token_embedding.weight".into(), &[s, info.n_vocab]);
+    add("decoder.ln.weight".into(), &[s]);
+    add("decoder.ln.bias".into(), &[s]);
+    for i in 0..info.n_text_layer {
+        let block = format!("decoder.blocks.{}", i);
+        add_block(&mut add, &block, s, true);
+    }
+    tensors
+}
+
+/// Tensors of a residual attention block of width `s`.
+fn add_block(add: &mut impl FnMut(String, &[i32]), block: &str, s: i32, cross_attention: bool) {
+    add(format!("{}.mlp_ln.weight", block), &[s]);
+    add(format!("{}.mlp_ln.bias", block), &[s]);
+    add(format!("{}.mlp.0.weight", block), &[s, 4 * s]);
+    add(format!("{}.mlp.0.bias", block), &[4 * s]);
+    add(format!("{}.mlp.2.weight", block), &[4 * s, s]);
+    add(format!("{}.mlp.2.bias", block), &[s]);
+    let attentions: &[&str] = if cross_attention {
+        &["attn", "cross_attn"]
+    } else {
+        &["attn"]
+    };
+    for attn in attentions {
+        add(format!("{}.{}_ln.weight", block, attn), &[s]);
+        add(format!("{}.{}_ln.bias", block, attn), &[s]);
+        add(format!("{}.{}.query.weight", block, attn), &[s, s]);
+        add(format!("{}.{}.query.bias", block, attn), &[s]);
+        // the key projection has no bias
+        add(format!("{}.{}.key.weight", block, attn), &[s, s]);
+        add(format!("{}.{}.value.weight", block, attn), &[s, s]);
+        add(format!("{}.{}.value.bias", block, attn), &[s]);
+        add(format!("{}.{}.out.weight", block, attn), &[s, s]);
+        add(format!("{}.{}.out.bias", block, attn), &[s]);
+    }
+}
+
+/// Check that the file at `path` is a complete ggml Whisper model, without loading the weights.
+///
+/// Checks the magic and hyperparameters, that the mel filters and vocabulary fit in the file,
+/// and that every tensor the hyperparameters imply is present exactly once, with the expected shape,
+/// and with all of its data in the file.
+///
+/// # Returns
+/// Ok([ModelInfo]) if the model looks intact, Err([ModelError]) describing the first problem found otherwise.
+pub fn verify_model<P: AsRef<Path>>(path: P) -> Result<ModelInfo, ModelError> {
+    let file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+    let mut reader = ModelReader {
+        reader: BufReader::new(file),
+        offset: 0,
+        file_size,
+    };
+
+    let mut info = ModelInfo::from_reader(&mut reader.reader).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => ModelError::Truncated {
+            section: "the header".to_string(),
+            expected: 48,
+            file_size,
+        },
+        io::ErrorKind::InvalidData => ModelError::InvalidHeader(e.to_string()),
+        _ => ModelError::Io(e),
+    })?;
+    info.file_size = file_size;
+    reader.offset = 48;
+
+    let n_mel = reader.read_i32("the mel filters")?;
+    let n_fft = reader.read_i32("the mel filters")?;
+    if n_mel != info.n_mels || n_fft <= 0 {
+        return Err(ModelError::InvalidHeader(format!(
+            "mel filters of {} x {}, expected {} mel bands",
+            n_mel, n_fft, info.n_mels
+        )));
+    }
+    reader.skip(n_mel as u64 * n_fft as u64 * 4, || {
+        "the mel filters".to_string()
+    })?;
+
+    let n_tokens = reader.read_i32("the vocabulary")?;
+    if n_tokens <= 0 || n_tokens > info.n_vocab {
+        return Err(ModelError::InvalidHeader(format!(
+            "vocabulary of {} tokens, expected at most {}",
+            n_tokens, info.n_vocab
+        )));
+    }
+    for i in 0..n_tokens {
+        let section = || format!("token {} of the vocabulary", i);
+        let len = reader.read_i32(&section())? as u32;
+        reader.skip(len as u64, section)?;
+    }
+
+    let mut expected = expected_tensors(&info);
+    let mut seen = HashMap::new();
+    while reader.offset < file_size {
+        let offset = reader.offset;
+        let invalid = |reason: String| ModelError::InvalidTensor { offset, reason };
+        let n_dims = reader.read_i32("a tensor header")?;
+        let name_len = reader.read_i32("a tensor header")?;
+        let ttype = reader.read_i32("a tensor header")?;
+        if !(1..=4).contains(&n_dims) {
+            return Err(invalid(format!("{} dimensions", n_dims)));
+        }
+        if name_len <= 0 || name_len as usize > MAX_TENSOR_NAME_LEN {
+            return Err(invalid(format!("name of {} bytes", name_len)));
+        }
+        let mut shape = Vec::with_capacity(n_dims as usize);
+        for _ in 0..n_dims {
+            let dim = reader.read_i32("a tensor header")?;
+            if dim <= 0 {
+                return Err(invalid(format!("dimension of size {}", dim)));
+            }
+            shape.push(dim as i64);
+        }
+        reader.require(name_len as u64, || "a tensor name".to_string())?;
+        let mut name = vec![0; name_len as usize];
+        reader.reader.read_exact(&mut name)?;
+        reader.offset += name_len as u64;
+        let name = String::from_utf8_lossy(&name).into_owned();
+
+        let n_elements = shape.iter().map(|&dim| dim as u64).product();
+        let size = tensor_size(ttype, n_elements, info.quantization_version).ok_or_else(|| {
+            invalid(format!(
+                "tensor {:?} of unknown type {} or incomplete blocks",
+                name, ttype
+            ))
+        })?;
+        reader.skip(size, || format!("the data of tensor {:?}", name))?;
+
+        if seen.insert(name.clone(), ()).is_some() {
+            return Err(ModelError::DuplicateTensor(name));
+        }
+        match expected.remove(&name) {
+            Some(expected) if expected == shape => {}
+            Some(expected) => {
+                return Err(ModelError::ShapeMismatch {
+                    name,
+                    expected,
+                    actual: shape,
+                })
+            }
+            None => return Err(ModelError::UnexpectedTensor(name)),
+        }
+    }
+
+    if !expected.is_empty() {
+        let mut missing: Vec<String> = expected.into_keys().collect();
+        missing.sort();
+        return Err(ModelError::MissingTensors(missing));
+    }
+    Ok(info)
+}
+
+/// The hash to compare a model file against with [verify_model_checksum].
+#[cfg(feature = "checksum")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModelChecksum {
+    /// Any of the [OFFICIAL_MODEL_SHA1] hashes.
+    Official,
+    /// A SHA-1 hash in hex.
+    Sha1(String),
+    /// A SHA-256 hash in hex.
+    Sha256(String),
+}
+
+/// Hash the whole model file and compare it against `checksum`.
+///
+/// Requires the `checksum` feature.
+///
+/// # Returns
+/// Ok(Some(name)) if the file is the official model `name`, Ok(None) if it matches an explicitly given hash,
+/// or Err([ModelError::ChecksumMismatch]) if it doesn't match.
+#[cfg(feature = "checksum")]
+pub fn verify_model_checksum<P: AsRef<Path>>(
+    path: P,
+    checksum: &ModelChecksum,
+) -> Result<Option<&'static str>, ModelError> {
+    use sha1::Digest;
+
+    fn hash<D: Digest>(path: &Path) -> io::Result<String> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut digest = D::new();
+        let mut buffer = vec![0; 1 << 20];
+        loop {
+            match reader.read(&mut buffer)? {
+                0 => break,
+                n => digest.update(&buffer[..n]),
+            }
+        }
+        Ok(digest
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect())
+    }
+
+    let path = path.as_ref();
+    let (expected, actual) = match checksum {
+        ModelChecksum::Official => {
+            let actual = hash::<sha1::Sha1>(path)?;
+            if let Some((name, _)) = OFFICIAL_MODEL_SHA1.iter().find(|(_, sha1)| *sha1 == actual) {
+                return Ok(Some(name));
+            }
+            ("an official model".to_string(), actual)
+        }
+        ModelChecksum::Sha1(expected) => (expected.to_ascii_lowercase(), hash::<sha1::Sha1>(path)?),
+        ModelChecksum::Sha256(expected) => {
+            (expected.to_ascii_lowercase(), hash::<sha2::Sha256>(path)?)
+        }
+    };
+    if expected == actual {
+        Ok(None)
+    } else {
+        Err(ModelError::ChecksumMismatch { expected, actual })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model_info::GGML_MAGIC;
+    use std::io::Write;
+
+    /// Write a tiny model with one encoder and one decoder layer, leaving out `skip` tensors.
+    fn write_model(path: &Path, skip: &[&str]) {
+        let hparams = [GGML_MAGIC as i32, 51864, 4, 8, 2, 1, 4, 8, 2, 1, 2, 1];
+        let mut bytes: Vec<u8> = hparams.iter().flat_map(|v| v.to_le_bytes()).collect();
+        // mel filters, 2 x 3
+        for v in [2i32, 3].into_iter().chain([0; 6]) {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        // vocabulary
+        bytes.extend_from_slice(&2i32.to_le_bytes());
+        for token in ["a", "bc"] {
+            bytes.extend_from_slice(&(token.len() as i32).to_le_bytes());
+            bytes.extend_from_slice(token.as_bytes());
+        }
+        let info = ModelInfo::from_reader(&bytes[..]).unwrap();
+        let mut tensors: Vec<_> = expected_tensors(&info).into_iter().collect();
+        tensors.sort();
+        for (name, shape) in tensors {
+            if skip.contains(&name.as_str()) {
+                continue;
+            }
+            for v in [shape.len() as i32, name.len() as i32, 0] {
+                bytes.extend_from_slice(&v.to_le_bytes());
+            }
+            for &dim in &shape {
+                bytes.extend_from_slice(&(dim as i32).to_le_bytes());
+            }
+            bytes.extend_from_slice(name.as_bytes());
+            let n: i64 = shape.iter().product();
+            bytes.resize(bytes.len() + n as usize * 4, 0);
+        }
+        File::create(path).unwrap().write_all(&bytes).unwrap();
+    }
+
+    #[test]
+    fn verify_synthetic_model() {
+        let path =
+            std::env::temp_dir().join(format!("whisper-rs-verify-{}.bin", std::process::id()));
+        write_model(&path, &[]);
+        let info = verify_model(&path).unwrap();
+        assert_eq!(info.n_audio_layer, 1);
+
+        let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(info.file_size - 3).unwrap();
+        assert!(matches!(
+            verify_model(&path),
+            Err(ModelError::Truncated { section, .. }) if section.starts_with("the data of tensor")
+        ));
+
+        write_model(&path, &["encoder.blocks.0.attn.key.weight"]);
+        assert!(matches!(
+            verify_model(&path),
+            Err(ModelError::MissingTensors(names)) if names == ["encoder.blocks.0.attn.key.weight"]
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
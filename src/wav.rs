@@ -0,0 +1,102 @@
+//! Decoding of WAV files into the 16 kHz mono samples Whisper expects.
+
+use crate::timestamp::WHISPER_SAMPLE_RATE;
+use std::fmt;
+
+/// Why [decode_wav] failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WavError {
+    /// The data is not a valid WAV file, with the message of the WAV reader.
+    Invalid(String),
+    /// The audio isn't sampled at 16 kHz. Holds the actual sample rate.
+    SampleRate(u32),
+}
+
+impl fmt::Display for WavError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invalid(e) => write!(f, "invalid WAV file: {}", e),
+            Self::SampleRate(rate) => write!(
+                f,
+                "expected a sample rate of {} Hz, got {} Hz; resample first, e.g. with `ffmpeg -i in.wav -ar 16000 out.wav`",
+                WHISPER_SAMPLE_RATE, rate
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WavError {}
+
+impl From<hound::Error> for WavError {
+    fn from(e: hound::Error) -> Self {
+        Self::Invalid(e.to_string())
+    }
+}
+
+/// Does the data look like a WAV file?
+pub fn is_wav(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WAVE")
+}
+
+/// Decode a 16 kHz WAV file of any sample format, mixing all channels down to mono.
+///
+/// # Arguments
+/// * bytes: The whole WAV file.
+///
+/// # Returns
+/// Ok(Vec<f32>) on success, Err(WavError) if the file is invalid or not sampled at 16 kHz.
+pub fn decode_wav(bytes: &[u8]) -> Result<Vec<f32>, WavError> {
+    let reader = hound::WavReader::new(bytes)?;
+    let spec = reader.spec();
+    if spec.sample_rate as usize != WHISPER_SAMPLE_RATE {
+        return Err(WavError::SampleRate(spec.sample_rate));
+    }
+    let samples: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.into_samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = (1_i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .into_samples::<i32>()
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+    let channels = spec.channels.max(1) as usize;
+    Ok(samples
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn wav(sample_rate: u32, channels: u16, samples: &[i16]) -> Vec<u8> {
+        let spec = hound::WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut out = Cursor::new(Vec::new());
+        let mut writer = hound::WavWriter::new(&mut out, spec).unwrap();
+        for &sample in samples {
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+        out.into_inner()
+    }
+
+    #[test]
+    fn decode_stereo() {
+        let bytes = wav(16000, 2, &[16384, 0, -16384, -16384]);
+        assert!(is_wav(&bytes));
+        assert_eq!(decode_wav(&bytes), Ok(vec![0.25, -0.5]));
+
+        let bytes = wav(44100, 1, &[0; 4]);
+        assert_eq!(decode_wav(&bytes), Err(WavError::SampleRate(44100)));
+        assert!(!is_wav(b"OggS"));
+    }
+}
@@ -1,21 +1,50 @@
-use crate::error::WhisperError;
+use crate::capture;
+use crate::error::{Operation, WhisperError};
+use crate::full_callbacks::FullCallbacks;
+use crate::logits::Logits;
+use crate::logits_processor::LogitsProcessor;
+use crate::segment::{Segment, SegmentToken};
+use crate::streaming::StreamingTranscription;
+use crate::timestamp::Timestamp;
+use crate::timings::{self, Timings};
+use crate::token_kind::{SpecialTokens, TokenKind};
+use crate::tokenizer::Tokenizer;
 use crate::whisper_params::FullParams;
 use crate::{WhisperToken, WhisperTokenData};
 use std::ffi::{c_int, CStr, CString};
+use std::fmt;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Enter a `tracing` span for the rest of the enclosing block, if the `tracing` feature is enabled.
+macro_rules! span {
+    ($($args:tt)*) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!($($args)*).entered();
+    };
+}
 
 /// Safe Rust wrapper around a Whisper context.
 ///
 /// You likely want to create this with [WhisperContext::new],
 /// then run a full transcription with [WhisperContext::full].
-#[derive(Debug)]
 pub struct WhisperContext {
-    ctx: *mut whisper_rs_sys::whisper_context,
+    pub(crate) ctx: *mut whisper_rs_sys::whisper_context,
     /// has the spectrogram been initialized in at least one way?
     spectrogram_initialized: bool,
     /// has the data been encoded?
     encode_complete: bool,
     /// has decode been called at least once?
     decode_once: bool,
+    /// number of tokens evaluated by the last decode call
+    n_decoded: usize,
+    /// tokens evaluated by the decoder so far, passed to the logits processors
+    history: Vec<WhisperToken>,
+    /// run in order after every successful decode
+    logits_processors: Vec<Box<dyn LogitsProcessor>>,
+    /// how long loading the model took, reported in [Timings]
+    load_time: Duration,
 }
 
 impl WhisperContext {
@@ -30,16 +59,37 @@ impl WhisperContext {
     /// # C++ equivalent
     /// `struct whisper_context * whisper_init_from_file(const char * path_model);`
     pub fn new(path: &str) -> Result<Self, WhisperError> {
-        let path_cstr = CString::new(path)?;
+        let operation = Operation::Init {
+            path: path.to_string(),
+        };
+        let checkpoint = capture::checkpoint();
+        let path_cstr = match CString::new(path) {
+            Ok(path_cstr) => path_cstr,
+            Err(e) => return Err(WhisperError::from(e).with_operation(operation, checkpoint)),
+        };
+        span!(
+            "whisper_init",
+            path,
+            n_vocab = tracing::field::Empty,
+            n_audio_ctx = tracing::field::Empty,
+            n_text_ctx = tracing::field::Empty
+        );
+        let start = Instant::now();
         let ctx = unsafe { whisper_rs_sys::whisper_init_from_file(path_cstr.as_ptr()) };
         if ctx.is_null() {
-            Err(WhisperError::InitError)
+            Err(WhisperError::InitError.with_operation(operation, checkpoint))
         } else {
+            #[cfg(feature = "tracing")]
+            record_model_dims(ctx);
             Ok(Self {
                 ctx,
                 spectrogram_initialized: false,
                 encode_complete: false,
                 decode_once: false,
+                n_decoded: 0,
+                history: Vec::new(),
+                logits_processors: Vec::new(),
+                load_time: start.elapsed(),
             })
         }
     }
@@ -55,21 +105,99 @@ impl WhisperContext {
     /// # C++ equivalent
     /// `struct whisper_context * whisper_init_from_buffer(const char * buffer, int n_bytes);`
     pub fn new_from_buffer(buffer: &[u8]) -> Result<Self, WhisperError> {
+        let operation = Operation::InitFromBuffer {
+            n_bytes: buffer.len(),
+        };
+        let checkpoint = capture::checkpoint();
+        span!(
+            "whisper_init",
+            n_bytes = buffer.len(),
+            n_vocab = tracing::field::Empty,
+            n_audio_ctx = tracing::field::Empty,
+            n_text_ctx = tracing::field::Empty
+        );
+        let start = Instant::now();
         let ctx =
             unsafe { whisper_rs_sys::whisper_init_from_buffer(buffer.as_ptr() as _, buffer.len()) };
         if ctx.is_null() {
-            Err(WhisperError::InitError)
+            Err(WhisperError::InitError.with_operation(operation, checkpoint))
         } else {
+            #[cfg(feature = "tracing")]
+            record_model_dims(ctx);
             Ok(Self {
                 ctx,
                 spectrogram_initialized: false,
                 encode_complete: false,
                 decode_once: false,
+                n_decoded: 0,
+                history: Vec::new(),
+                logits_processors: Vec::new(),
+                load_time: start.elapsed(),
             })
         }
     }
 
-    // we don't implement `whisper_init()` here since i have zero clue what `whisper_model_loader` does
+    /// Create a new WhisperContext from a memory-mapped model file.
+    ///
+    /// whisper.cpp copies the weights into its own tensors straight from the mapped pages,
+    /// so unlike [new_from_buffer](WhisperContext::new_from_buffer) the file is never held in a
+    /// separate buffer. Pages that were copied are released from the memory of the process
+    /// while loading continues (on Unix), so they don't count towards its resident size next to the tensors.
+    /// The file must not be modified while the model loads.
+    ///
+    /// Requires the `mmap` feature.
+    ///
+    /// # Arguments
+    /// * path: The path to the model file.
+    ///
+    /// # Returns
+    /// Ok(Self) on success, Err(WhisperError) on failure.
+    /// [WhisperError::FailedToMapModel] if the file can't be opened or mapped.
+    ///
+    /// # C++ equivalent
+    /// `struct whisper_context * whisper_init(struct whisper_model_loader * loader);`
+    #[cfg(feature = "mmap")]
+    pub fn new_from_mmap<P: AsRef<std::path::Path>>(path: P) -> Result<Self, WhisperError> {
+        let path = path.as_ref();
+        let operation = Operation::InitFromMmap {
+            path: path.to_string_lossy().into_owned(),
+        };
+        let checkpoint = capture::checkpoint();
+        span!(
+            "whisper_init",
+            path = %path.display(),
+            n_vocab = tracing::field::Empty,
+            n_audio_ctx = tracing::field::Empty,
+            n_text_ctx = tracing::field::Empty
+        );
+        let start = Instant::now();
+        let mut mapped = match crate::mmap_loader::MmapLoader::open(path) {
+            Ok(mapped) => mapped,
+            Err(e) => {
+                return Err(WhisperError::FailedToMapModel(e.to_string())
+                    .with_operation(operation, checkpoint))
+            }
+        };
+        let mut loader = mapped.loader();
+        let ctx = unsafe { whisper_rs_sys::whisper_init(&mut loader) };
+        drop(mapped);
+        if ctx.is_null() {
+            Err(WhisperError::InitError.with_operation(operation, checkpoint))
+        } else {
+            #[cfg(feature = "tracing")]
+            record_model_dims(ctx);
+            Ok(Self {
+                ctx,
+                spectrogram_initialized: false,
+                encode_complete: false,
+                decode_once: false,
+                n_decoded: 0,
+                history: Vec::new(),
+                logits_processors: Vec::new(),
+                load_time: start.elapsed(),
+            })
+        }
+    }
 
     /// Convert raw PCM audio (floating point 32 bit) to log mel spectrogram.
     /// The resulting spectrogram is stored in the context transparently.
@@ -84,8 +212,19 @@ impl WhisperContext {
     /// # C++ equivalent
     /// `int whisper_pcm_to_mel(struct whisper_context * ctx, const float * samples, int n_samples, int n_threads)`
     pub fn pcm_to_mel(&mut self, pcm: &[f32], threads: usize) -> Result<(), WhisperError> {
+        let operation = Operation::PcmToMel {
+            n_samples: pcm.len(),
+            threads,
+        };
+        let checkpoint = capture::checkpoint();
+        span!(
+            "whisper_pcm_to_mel",
+            n_samples = pcm.len(),
+            audio_secs = pcm.len() as f64 / crate::WHISPER_SAMPLE_RATE as f64,
+            threads
+        );
         if threads < 1 {
-            return Err(WhisperError::InvalidThreadCount);
+            return Err(WhisperError::InvalidThreadCount.with_operation(operation, checkpoint));
         }
         let ret = unsafe {
             whisper_rs_sys::whisper_pcm_to_mel(
@@ -96,12 +235,12 @@ impl WhisperContext {
             )
         };
         if ret == -1 {
-            Err(WhisperError::UnableToCalculateSpectrogram)
+            Err(WhisperError::UnableToCalculateSpectrogram.with_operation(operation, checkpoint))
         } else if ret == 0 {
             self.spectrogram_initialized = true;
             Ok(())
         } else {
-            Err(WhisperError::GenericError(ret))
+            Err(WhisperError::GenericError(ret).with_operation(operation, checkpoint))
         }
     }
 
@@ -122,6 +261,8 @@ impl WhisperContext {
     /// # C++ equivalent
     /// `int whisper_set_mel(struct whisper_context * ctx, const float * data, int n_len, int n_mel)`
     pub fn set_mel(&mut self, data: &[f32]) -> Result<(), WhisperError> {
+        let operation = Operation::SetMel { n_len: data.len() };
+        let checkpoint = capture::checkpoint();
         let ret = unsafe {
             whisper_rs_sys::whisper_set_mel(
                 self.ctx,
@@ -131,12 +272,12 @@ impl WhisperContext {
             )
         };
         if ret == -1 {
-            Err(WhisperError::InvalidMelBands)
+            Err(WhisperError::InvalidMelBands.with_operation(operation, checkpoint))
         } else if ret == 0 {
             self.spectrogram_initialized = true;
             Ok(())
         } else {
-            Err(WhisperError::GenericError(ret))
+            Err(WhisperError::GenericError(ret).with_operation(operation, checkpoint))
         }
     }
 
@@ -153,21 +294,32 @@ impl WhisperContext {
     /// # C++ equivalent
     /// `int whisper_encode(struct whisper_context * ctx, int offset, int n_threads)`
     pub fn encode(&mut self, offset: usize, threads: usize) -> Result<(), WhisperError> {
+        let operation = Operation::Encode { offset, threads };
+        let checkpoint = capture::checkpoint();
+        span!(
+            "whisper_encode",
+            offset,
+            threads,
+            n_audio_ctx = self.n_audio_ctx()
+        );
         if !self.spectrogram_initialized {
-            return Err(WhisperError::SpectrogramNotInitialized);
+            return Err(
+                WhisperError::SpectrogramNotInitialized.with_operation(operation, checkpoint)
+            );
         }
         if threads < 1 {
-            return Err(WhisperError::InvalidThreadCount);
+            return Err(WhisperError::InvalidThreadCount.with_operation(operation, checkpoint));
         }
         let ret =
             unsafe { whisper_rs_sys::whisper_encode(self.ctx, offset as c_int, threads as c_int) };
+        self.reset_decoder();
         if ret == -1 {
-            Err(WhisperError::UnableToCalculateEvaluation)
+            Err(WhisperError::UnableToCalculateEvaluation.with_operation(operation, checkpoint))
         } else if ret == 0 {
             self.encode_complete = true;
             Ok(())
         } else {
-            Err(WhisperError::GenericError(ret))
+            Err(WhisperError::GenericError(ret).with_operation(operation, checkpoint))
         }
     }
 
@@ -175,10 +327,14 @@ impl WhisperContext {
     /// Make sure to call [WhisperContext::encode] first.
     /// tokens + n_tokens is the provided context for the decoder.
     ///
+    /// Any logits processors added with [WhisperContext::add_logits_processor] are run on the
+    /// logits for the next token before this returns.
+    ///
     /// # Arguments
     /// * tokens: The tokens to decode.
     /// * n_tokens: The number of tokens to decode.
     /// * n_past: The number of past tokens to use for the decoding.
+    ///   At most the number of tokens decoded since the last encode, returns an error otherwise.
     /// * n_threads: How many threads to use. Defaults to 1. Must be at least 1, returns an error otherwise.
     ///
     /// # Returns
@@ -192,11 +348,31 @@ impl WhisperContext {
         n_past: usize,
         threads: usize,
     ) -> Result<(), WhisperError> {
+        let operation = Operation::Decode {
+            n_tokens: tokens.len(),
+            n_past,
+            threads,
+        };
+        let checkpoint = capture::checkpoint();
+        if n_past > self.history.len() {
+            let error = WhisperError::NPastTooLarge {
+                n_past,
+                n_known: self.history.len(),
+            };
+            return Err(error.with_operation(operation, checkpoint));
+        }
+        span!(
+            "whisper_decode",
+            n_tokens = tokens.len(),
+            n_past,
+            threads,
+            n_vocab = self.n_vocab()
+        );
         if !self.encode_complete {
-            return Err(WhisperError::EncodeNotComplete);
+            return Err(WhisperError::EncodeNotComplete.with_operation(operation, checkpoint));
         }
         if threads < 1 {
-            return Err(WhisperError::InvalidThreadCount);
+            return Err(WhisperError::InvalidThreadCount.with_operation(operation, checkpoint));
         }
         let ret = unsafe {
             whisper_rs_sys::whisper_decode(
@@ -208,49 +384,135 @@ impl WhisperContext {
             )
         };
         if ret == -1 {
-            Err(WhisperError::UnableToCalculateEvaluation)
+            Err(WhisperError::UnableToCalculateEvaluation.with_operation(operation, checkpoint))
         } else if ret == 0 {
             self.decode_once = true;
+            self.n_decoded = tokens.len();
+            self.history.truncate(n_past);
+            self.history.extend_from_slice(tokens);
+            self.run_logits_processors();
             Ok(())
         } else {
-            Err(WhisperError::GenericError(ret))
+            Err(WhisperError::GenericError(ret).with_operation(operation, checkpoint))
         }
     }
 
+    /// Add a logits processor to run after every call to [WhisperContext::decode].
+    ///
+    /// Processors are run in the order they were added.
+    ///
+    /// # Arguments
+    /// * processor: The processor to add. See [crate::LogitsProcessor].
+    pub fn add_logits_processor(&mut self, processor: impl LogitsProcessor + 'static) {
+        self.logits_processors.push(Box::new(processor));
+    }
+
+    /// Remove all logits processors added with [WhisperContext::add_logits_processor].
+    pub fn clear_logits_processors(&mut self) {
+        self.logits_processors.clear();
+    }
+
+    /// Apply the logits processors to the logits for the next token.
+    fn run_logits_processors(&mut self) {
+        if self.logits_processors.is_empty() || self.n_decoded == 0 {
+            return;
+        }
+        let logits = unsafe { whisper_rs_sys::whisper_get_logits(self.ctx) };
+        if logits.is_null() {
+            return;
+        }
+        let n_vocab = self.n_vocab() as usize;
+        // SAFETY: whisper_decode stores the n_vocab logits of the last token only,
+        // and we hold &mut self so nothing else can access them
+        let last = unsafe { std::slice::from_raw_parts_mut(logits, n_vocab) };
+        for processor in &mut self.logits_processors {
+            processor.process(&self.history, last);
+        }
+    }
+
+    /// Forget the last decode call. Running the encoder, or a full transcription, overwrites
+    /// the decoder state and the logits whisper.cpp keeps for it.
+    fn reset_decoder(&mut self) {
+        self.decode_once = false;
+        self.n_decoded = 0;
+        self.history.clear();
+    }
+
     /// Convert the provided text into tokens.
     ///
+    /// The token buffer is sized automatically, and grown if whisper.cpp reports that the result
+    /// doesn't fit.
+    ///
     /// # Arguments
-    /// * text: The text to convert.
+    /// * text: The text to convert. Must not contain null bytes.
     ///
     /// # Returns
     /// Ok(Vec<WhisperToken>) on success, Err(WhisperError) on failure.
     ///
     /// # C++ equivalent
     /// `int whisper_tokenize(struct whisper_context * ctx, const char * text, whisper_token * tokens, int n_max_tokens);`
-    pub fn tokenize(
-        &mut self,
-        text: &str,
-        max_tokens: usize,
-    ) -> Result<Vec<WhisperToken>, WhisperError> {
-        // allocate at least max_tokens to ensure the memory is valid
-        let mut tokens: Vec<WhisperToken> = Vec::with_capacity(max_tokens);
-        let ret = unsafe {
-            whisper_rs_sys::whisper_tokenize(
-                self.ctx,
-                text.as_ptr() as *const _,
-                tokens.as_mut_ptr(),
-                max_tokens as c_int,
-            )
+    pub fn tokenize(&self, text: &str) -> Result<Vec<WhisperToken>, WhisperError> {
+        let operation = Operation::Tokenize {
+            text_len: text.len(),
         };
-        if ret == -1 {
-            Err(WhisperError::InvalidText)
-        } else {
-            // SAFETY: when ret != -1, we know that the length of the vector is at least ret tokens
-            unsafe { tokens.set_len(ret as usize) };
-            Ok(tokens)
+        let checkpoint = capture::checkpoint();
+        let c_text = match CString::new(text) {
+            Ok(c_text) => c_text,
+            Err(e) => return Err(WhisperError::from(e).with_operation(operation, checkpoint)),
+        };
+        // byte-level BPE never produces more tokens than there are bytes,
+        // so the buffer only has to grow up to text.len(); start with a typical size
+        let max_len = text.len().max(1);
+        let mut max_tokens = (text.len() / 3 + 8).min(max_len);
+        loop {
+            // allocate at least max_tokens to ensure the memory is valid
+            let mut tokens: Vec<WhisperToken> = Vec::with_capacity(max_tokens);
+            let ret = unsafe {
+                whisper_rs_sys::whisper_tokenize(
+                    self.ctx,
+                    c_text.as_ptr(),
+                    tokens.as_mut_ptr(),
+                    max_tokens as c_int,
+                )
+            };
+            if ret >= 0 {
+                // SAFETY: whisper.cpp wrote ret tokens, and ret <= max_tokens
+                unsafe { tokens.set_len(ret as usize) };
+                return Ok(tokens);
+            }
+            if max_tokens >= max_len {
+                return Err(WhisperError::InvalidText.with_operation(operation, checkpoint));
+            }
+            // newer versions of whisper.cpp return the negated number of tokens required,
+            // older ones return -1
+            let required = ret.unsigned_abs() as usize;
+            max_tokens = if required > max_tokens {
+                required
+            } else {
+                max_tokens * 2
+            }
+            .min(max_len);
         }
     }
 
+    /// Convert several texts into tokens with [WhisperContext::tokenize].
+    ///
+    /// # Arguments
+    /// * texts: The texts to convert.
+    ///
+    /// # Returns
+    /// Ok(Vec<Vec<WhisperToken>>) with one entry per text on success, Err(WhisperError) on the first failure.
+    pub fn tokenize_many<I, S>(&self, texts: I) -> Result<Vec<Vec<WhisperToken>>, WhisperError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        texts
+            .into_iter()
+            .map(|text| self.tokenize(text.as_ref()))
+            .collect()
+    }
+
     // Language functions
     /// Use mel data at offset_ms to try and auto-detect the spoken language
     /// Make sure to call pcm_to_mel() or set_mel() first
@@ -269,11 +531,16 @@ impl WhisperContext {
         offset_ms: usize,
         threads: usize,
     ) -> Result<Vec<f32>, WhisperError> {
+        let operation = Operation::LangDetect { offset_ms, threads };
+        let checkpoint = capture::checkpoint();
+        span!("whisper_lang_detect", offset_ms, threads);
         if !self.spectrogram_initialized {
-            return Err(WhisperError::SpectrogramNotInitialized);
+            return Err(
+                WhisperError::SpectrogramNotInitialized.with_operation(operation, checkpoint)
+            );
         }
         if threads < 1 {
-            return Err(WhisperError::InvalidThreadCount);
+            return Err(WhisperError::InvalidThreadCount.with_operation(operation, checkpoint));
         }
         let mut lang_probs: Vec<f32> = vec![0.0; crate::standalone::get_lang_max_id() as usize + 1];
         let ret = unsafe {
@@ -284,8 +551,10 @@ impl WhisperContext {
                 lang_probs.as_mut_ptr(),
             )
         };
+        // language detection runs the encoder and decodes a single token
+        self.reset_decoder();
         if ret == -1 {
-            Err(WhisperError::UnableToCalculateEvaluation)
+            Err(WhisperError::UnableToCalculateEvaluation.with_operation(operation, checkpoint))
         } else {
             assert_eq!(
                 ret as usize,
@@ -363,46 +632,59 @@ impl WhisperContext {
 
     // logit functions
     /// Get the logits obtained from the last call to [WhisperContext::decode].
-    /// The logits for the last token are stored in the last row of the matrix.
     ///
-    /// Note: this function may be somewhat expensive depending on the size of the matrix returned, as it
-    /// needs to be rebuilt from the raw data. Try to avoid calling it more than once if possible.
+    /// whisper.cpp only keeps the logits for the last token passed to decode, so the returned view
+    /// has a single row of n_vocab columns: the logits for the next token, see [crate::Logits::last].
+    /// No data is copied.
     ///
-    /// # Arguments
-    /// * segment: The segment to fetch data for.
+    /// Running [WhisperContext::encode], [WhisperContext::lang_detect] or a full transcription
+    /// discards the logits, this returns [WhisperError::DecodeNotComplete] until the next decode.
     ///
     /// # Returns
-    /// 2D matrix of logits. Row count is equal to n_tokens, column count is equal to n_vocab.
+    /// Ok(Logits) on success, Err(WhisperError) on failure.
     ///
     /// # C++ equivalent
     /// `float * whisper_get_logits(struct whisper_context * ctx)`
-    pub fn get_logits(&self, segment: c_int) -> Result<Vec<Vec<f32>>, WhisperError> {
-        if !self.spectrogram_initialized {
-            return Err(WhisperError::SpectrogramNotInitialized);
+    pub fn logits(&self) -> Result<Logits<'_>, WhisperError> {
+        // a decode call without tokens doesn't produce logits
+        if !self.decode_once || self.n_decoded == 0 {
+            return Err(WhisperError::DecodeNotComplete);
         }
 
         let ret = unsafe { whisper_rs_sys::whisper_get_logits(self.ctx) };
         if ret.is_null() {
             return Err(WhisperError::NullPointer);
         }
-        let mut logits = Vec::new();
-        let n_vocab = self.n_vocab();
-        let n_tokens = self.full_n_tokens(segment);
-        for i in 0..n_tokens {
-            let mut row = Vec::new();
-            for j in 0..n_vocab {
-                let idx = (i * n_vocab) + j;
-                let val = unsafe { *ret.offset(idx as isize) };
-                row.push(val);
-            }
-            logits.push(row);
-        }
-        Ok(logits)
+        let n_vocab = self.n_vocab() as usize;
+        // SAFETY: whisper_decode stores the n_vocab logits of the last token only,
+        // which stay valid until the next call that runs the model, which requires &mut self
+        let data = unsafe { std::slice::from_raw_parts(ret, n_vocab) };
+        Ok(Logits::new(data, n_vocab))
+    }
+
+    /// Get the logits obtained from the last call to [WhisperContext::decode], copied into a matrix.
+    ///
+    /// # Arguments
+    /// * segment: Unused.
+    ///
+    /// # Returns
+    /// 2D matrix of logits with a single row, the logits for the next token, see [WhisperContext::logits].
+    /// Column count is equal to n_vocab.
+    ///
+    /// # C++ equivalent
+    /// `float * whisper_get_logits(struct whisper_context * ctx)`
+    #[deprecated(note = "use `WhisperContext::logits` instead, which does not copy")]
+    pub fn get_logits(&self, _segment: c_int) -> Result<Vec<Vec<f32>>, WhisperError> {
+        Ok(self.logits()?.rows().map(|row| row.to_vec()).collect())
     }
 
     // token functions
     /// Convert a token ID to a string.
     ///
+    /// Tokens may hold only part of a multi-byte UTF-8 character, in which case this returns
+    /// [WhisperError::InvalidUtf8]. Use [WhisperContext::token_to_bytes] or [WhisperContext::tokenizer]
+    /// to decode sequences of tokens instead.
+    ///
     /// # Arguments
     /// * token_id: ID of the token.
     ///
@@ -421,6 +703,31 @@ impl WhisperContext {
         Ok(r_str.to_string())
     }
 
+    /// Get the raw bytes of a token.
+    ///
+    /// # Arguments
+    /// * token_id: ID of the token.
+    ///
+    /// # Returns
+    /// Ok(&[u8]) on success, Err(WhisperError) on failure.
+    ///
+    /// # C++ equivalent
+    /// `const char * whisper_token_to_str(struct whisper_context * ctx, whisper_token token)`
+    pub fn token_to_bytes(&self, token_id: WhisperToken) -> Result<&[u8], WhisperError> {
+        let ret = unsafe { whisper_rs_sys::whisper_token_to_str(self.ctx, token_id) };
+        if ret.is_null() {
+            return Err(WhisperError::NullPointer);
+        }
+        // SAFETY: the string is owned by the vocabulary, which lives as long as the context
+        let c_str = unsafe { CStr::from_ptr(ret) };
+        Ok(c_str.to_bytes())
+    }
+
+    /// Get a byte-level [Tokenizer] for this model's vocabulary.
+    pub fn tokenizer(&self) -> Tokenizer<'_> {
+        Tokenizer::new(self)
+    }
+
     /// Get the ID of the eot token.
     ///
     /// # C++ equivalent
@@ -487,6 +794,46 @@ impl WhisperContext {
         unsafe { whisper_rs_sys::whisper_token_lang(self.ctx, lang_id) }
     }
 
+    /// Get the ID of the translate task token.
+    ///
+    /// Unlike [crate::token_translate], this takes the vocabulary of the model into account,
+    /// so it is also correct for English-only models.
+    #[inline]
+    pub fn token_translate(&self) -> WhisperToken {
+        self.special_tokens().translate()
+    }
+
+    /// Get the ID of the transcribe task token.
+    ///
+    /// Unlike [crate::token_transcribe], this takes the vocabulary of the model into account,
+    /// so it is also correct for English-only models.
+    #[inline]
+    pub fn token_transcribe(&self) -> WhisperToken {
+        self.special_tokens().transcribe()
+    }
+
+    fn special_tokens(&self) -> SpecialTokens {
+        SpecialTokens {
+            n_vocab: self.n_vocab(),
+            eot: self.token_eot(),
+            sot: self.token_sot(),
+            prev: self.token_prev(),
+            not: self.token_not(),
+            beg: self.token_beg(),
+        }
+    }
+
+    /// Classify a token ID as text or one of the special tokens.
+    ///
+    /// # Arguments
+    /// * token: ID of the token.
+    ///
+    /// # Returns
+    /// Some([crate::TokenKind]), or None if the ID is outside the vocabulary.
+    pub fn classify(&self, token: WhisperToken) -> Option<TokenKind> {
+        self.special_tokens().classify(token)
+    }
+
     /// Print performance statistics to stderr.
     ///
     /// # C++ equivalent
@@ -515,31 +862,111 @@ impl WhisperContext {
     /// * pcm: PCM audio data.
     ///
     /// # Returns
-    /// Ok(c_int) on success, Err(WhisperError) on failure.
+    /// Ok([Timings]) on success, Err(WhisperError) on failure.
     ///
     /// # C++ equivalent
     /// `int whisper_full(struct whisper_context * ctx, struct whisper_full_params params, const float * samples, int n_samples)`
-    pub fn full(&mut self, params: FullParams, data: &[f32]) -> Result<c_int, WhisperError> {
+    pub fn full(&mut self, params: FullParams, data: &[f32]) -> Result<Timings, WhisperError> {
+        let operation = Operation::Full {
+            n_samples: data.len(),
+            threads: params.fp.n_threads,
+        };
+        let checkpoint = capture::checkpoint();
+        span!(
+            "whisper_full",
+            n_samples = data.len(),
+            audio_secs = data.len() as f64 / crate::WHISPER_SAMPLE_RATE as f64,
+            threads = params.fp.n_threads,
+            n_vocab = self.n_vocab(),
+            n_audio_ctx = self.n_audio_ctx(),
+            n_text_ctx = self.n_text_ctx()
+        );
+        let mut params = params;
+        // SAFETY: the callbacks outlive the call
+        let callbacks = unsafe { FullCallbacks::install(&mut params, self, data.len()) };
+        self.reset_timings();
         let ret = unsafe {
             whisper_rs_sys::whisper_full(self.ctx, params.fp, data.as_ptr(), data.len() as c_int)
         };
-        if ret == -1 {
-            Err(WhisperError::UnableToCalculateSpectrogram)
-        } else if ret == 7 {
-            Err(WhisperError::FailedToEncode)
-        } else if ret == 8 {
-            Err(WhisperError::FailedToDecode)
+        self.reset_decoder();
+        callbacks.resume_panic();
+        if ret == 0 && callbacks.timed_out() {
+            let segments = self.full_get_segments()?;
+            let position = segments.last().map_or(
+                Timestamp::from(Duration::from_millis(params.fp.offset_ms.max(0) as u64)),
+                |segment| segment.t1,
+            );
+            Err(WhisperError::Timeout { segments, position }.with_operation(operation, checkpoint))
+        } else if ret == 0 && callbacks.aborted() {
+            Err(WhisperError::Aborted.with_operation(operation, checkpoint))
         } else if ret == 0 {
-            Ok(ret)
+            callbacks.finish();
+            let encode_decode = timings::read_encode_decode(self.ctx);
+            Ok(callbacks.timings(self.load_time, data.len(), encode_decode))
         } else {
-            Err(WhisperError::GenericError(ret))
+            Err(WhisperError::from_full_code(ret).with_operation(operation, checkpoint))
         }
     }
 
+    /// Run [WhisperContext::full], stopping once `timeout` has passed.
+    ///
+    /// The deadline is checked before the encoder runs on every 30 second window,
+    /// so the call can take longer than `timeout` by the time to transcribe one window.
+    ///
+    /// # Arguments
+    /// * params: [crate::FullParams] struct.
+    /// * data: PCM audio data.
+    /// * timeout: Wall-clock time the call may take.
+    ///
+    /// # Returns
+    /// Ok([Timings]) on success, Err(WhisperError) on failure.
+    /// On timeout the root cause is [WhisperError::Timeout], holding the segments decoded so far,
+    /// and the end of the last one as the position to retry the rest of the audio from,
+    /// for example with [FullParams::set_offset_ms](crate::FullParams::set_offset_ms).
+    pub fn full_with_timeout(
+        &mut self,
+        params: FullParams,
+        data: &[f32],
+        timeout: Duration,
+    ) -> Result<Timings, WhisperError> {
+        let mut params = params;
+        params.deadline = Some(Instant::now() + timeout);
+        self.full(params, data)
+    }
+
+    /// Run [WhisperContext::full] on a background thread, receiving segments while they are decoded.
+    ///
+    /// # Arguments
+    /// * params: [crate::FullParams] struct. Borrowed prompt tokens must be `'static`, since the call outlives this function.
+    ///   Any segment callback or abort flag set on it is replaced.
+    /// * audio: 16 kHz mono PCM audio data.
+    ///
+    /// # Returns
+    /// A [StreamingTranscription] to iterate the segments of, and join to get the context back.
+    pub fn full_streaming(
+        self,
+        mut params: FullParams<'static, 'static>,
+        audio: Vec<f32>,
+    ) -> StreamingTranscription {
+        let abort = Arc::new(AtomicBool::new(false));
+        params.set_abort_flag(Arc::clone(&abort));
+        StreamingTranscription::spawn(self, abort, move |ctx, sender| {
+            params.set_segment_callback(move |segment| {
+                // the receiver may be gone, the transcription still finishes
+                let _ = sender.send(segment);
+            });
+            ctx.full(params, &audio)
+        })
+    }
+
     /// Split the input audio into chunks and delegate to [WhisperContext::full].
     ///
     /// It seems this approach can offer some speedup in some cases,
     /// however, the accuracy can be worse at the start and end of chunks.
+    /// See [LongFormTranscriber](crate::LongFormTranscriber) for chunking that cuts at quiet points
+    /// and stitches the chunks back together.
+    /// See [ContextPool::transcribe_parallel](crate::ContextPool::transcribe_parallel) to run chunks on separate contexts,
+    /// failing when any of them fails.
     ///
     /// # Arguments
     /// * params: [crate::FullParams] struct.
@@ -547,7 +974,8 @@ impl WhisperContext {
     /// * n_processors: Number of threads to use.
     ///
     /// # Returns
-    /// Ok(c_int) on success, Err(WhisperError) on failure.
+    /// Ok([Timings]) on success, Err(WhisperError) on failure.
+    /// The encoder runs, and the encode and decode time of all chunks are added up.
     ///
     /// # C++ equivalent
     /// `int whisper_full_parallel(struct whisper_context * ctx, struct whisper_full_params params, const float * samples, int n_samples, int n_processors)`
@@ -556,7 +984,27 @@ impl WhisperContext {
         params: FullParams,
         data: &[f32],
         n_processors: c_int,
-    ) -> Result<c_int, WhisperError> {
+    ) -> Result<Timings, WhisperError> {
+        let operation = Operation::FullParallel {
+            n_samples: data.len(),
+            threads: params.fp.n_threads,
+            n_processors,
+        };
+        let checkpoint = capture::checkpoint();
+        span!(
+            "whisper_full_parallel",
+            n_samples = data.len(),
+            audio_secs = data.len() as f64 / crate::WHISPER_SAMPLE_RATE as f64,
+            threads = params.fp.n_threads,
+            n_processors,
+            n_vocab = self.n_vocab(),
+            n_audio_ctx = self.n_audio_ctx(),
+            n_text_ctx = self.n_text_ctx()
+        );
+        let mut params = params;
+        // SAFETY: the callbacks outlive the call
+        let callbacks = unsafe { FullCallbacks::install(&mut params, self, data.len()) };
+        self.reset_timings();
         let ret = unsafe {
             whisper_rs_sys::whisper_full_parallel(
                 self.ctx,
@@ -566,18 +1014,18 @@ impl WhisperContext {
                 n_processors,
             )
         };
-        if ret == -1 {
-            Err(WhisperError::UnableToCalculateSpectrogram)
-        } else if ret == 7 {
-            Err(WhisperError::FailedToEncode)
-        } else if ret == 8 {
-            Err(WhisperError::FailedToDecode)
+        self.reset_decoder();
+        callbacks.resume_panic();
+        if ret == 0 && callbacks.aborted() {
+            Err(WhisperError::Aborted.with_operation(operation, checkpoint))
         } else if ret == 0 {
             // note 0 is returned on success and also when initializing other contexts fails,
             // causing some audio to not be processed
-            Ok(ret)
+            callbacks.finish();
+            let encode_decode = timings::read_encode_decode(self.ctx);
+            Ok(callbacks.timings(self.load_time, data.len(), encode_decode))
         } else {
-            Err(WhisperError::GenericError(ret))
+            Err(WhisperError::from_full_code(ret).with_operation(operation, checkpoint))
         }
     }
 
@@ -593,26 +1041,34 @@ impl WhisperContext {
 
     /// Get the start time of the specified segment.
     ///
+    /// The time is absolute within the input audio, see [crate::Timestamp].
+    ///
     /// # Arguments
     /// * segment: Segment index.
     ///
     /// # C++ equivalent
     /// `int64_t whisper_full_get_segment_t0(struct whisper_context * ctx, int i_segment)`
     #[inline]
-    pub fn full_get_segment_t0(&self, segment: c_int) -> i64 {
-        unsafe { whisper_rs_sys::whisper_full_get_segment_t0(self.ctx, segment) }
+    pub fn full_get_segment_t0(&self, segment: c_int) -> Timestamp {
+        Timestamp::from_centis(unsafe {
+            whisper_rs_sys::whisper_full_get_segment_t0(self.ctx, segment)
+        })
     }
 
     /// Get the end time of the specified segment.
     ///
+    /// The time is absolute within the input audio, see [crate::Timestamp].
+    ///
     /// # Arguments
     /// * segment: Segment index.
     ///
     /// # C++ equivalent
     /// `int64_t whisper_full_get_segment_t1(struct whisper_context * ctx, int i_segment)`
     #[inline]
-    pub fn full_get_segment_t1(&self, segment: c_int) -> i64 {
-        unsafe { whisper_rs_sys::whisper_full_get_segment_t1(self.ctx, segment) }
+    pub fn full_get_segment_t1(&self, segment: c_int) -> Timestamp {
+        Timestamp::from_centis(unsafe {
+            whisper_rs_sys::whisper_full_get_segment_t1(self.ctx, segment)
+        })
     }
 
     /// Get the text of the specified segment.
@@ -635,6 +1091,55 @@ impl WhisperContext {
         Ok(r_str.to_string())
     }
 
+    /// Copy the specified segment, including all of its tokens, out of the context.
+    ///
+    /// # Arguments
+    /// * segment: Segment index.
+    ///
+    /// # Returns
+    /// Ok(Segment) on success, Err(WhisperError) on failure.
+    pub fn full_get_segment(&self, segment: c_int) -> Result<Segment, WhisperError> {
+        let ret = unsafe { whisper_rs_sys::whisper_full_get_segment_text(self.ctx, segment) };
+        if ret.is_null() {
+            return Err(WhisperError::NullPointer);
+        }
+        let text = unsafe { CStr::from_ptr(ret) }
+            .to_string_lossy()
+            .into_owned();
+        let special_tokens = self.special_tokens();
+        let tokens = (0..self.full_n_tokens(segment))
+            .map(|token| {
+                let data = self.full_get_token_data(segment, token);
+                let bytes = self.full_get_token_bytes(segment, token)?.to_vec();
+                Ok(SegmentToken {
+                    id: data.id,
+                    // whisper.cpp only produces tokens of the vocabulary
+                    kind: special_tokens
+                        .classify(data.id)
+                        .ok_or(WhisperError::NullPointer)?,
+                    bytes,
+                    data,
+                })
+            })
+            .collect::<Result<_, WhisperError>>()?;
+        Ok(Segment {
+            t0: self.full_get_segment_t0(segment),
+            t1: self.full_get_segment_t1(segment),
+            text,
+            tokens,
+        })
+    }
+
+    /// Copy all segments out of the context. See [WhisperContext::full_get_segment].
+    ///
+    /// # Returns
+    /// Ok(Vec<Segment>) on success, Err(WhisperError) on failure.
+    pub fn full_get_segments(&self) -> Result<Vec<Segment>, WhisperError> {
+        (0..self.full_n_segments())
+            .map(|segment| self.full_get_segment(segment))
+            .collect()
+    }
+
     /// Get number of tokens in the specified segment.
     ///
     /// # Arguments
@@ -675,6 +1180,34 @@ impl WhisperContext {
         Ok(r_str.to_string())
     }
 
+    /// Get the raw bytes of the specified token in the specified segment.
+    ///
+    /// Unlike [WhisperContext::full_get_token_text] this never fails on tokens holding
+    /// part of a multi-byte UTF-8 character.
+    ///
+    /// # Arguments
+    /// * segment: Segment index.
+    /// * token: Token index.
+    ///
+    /// # Returns
+    /// Ok(&[u8]) on success, Err(WhisperError) on failure.
+    ///
+    /// # C++ equivalent
+    /// `const char * whisper_full_get_token_text(struct whisper_context * ctx, int i_segment, int i_token)`
+    pub fn full_get_token_bytes(
+        &self,
+        segment: c_int,
+        token: c_int,
+    ) -> Result<&[u8], WhisperError> {
+        let ret = unsafe { whisper_rs_sys::whisper_full_get_token_text(self.ctx, segment, token) };
+        if ret.is_null() {
+            return Err(WhisperError::NullPointer);
+        }
+        // SAFETY: the string is owned by the vocabulary, which lives as long as the context
+        let c_str = unsafe { CStr::from_ptr(ret) };
+        Ok(c_str.to_bytes())
+    }
+
     /// Get the token ID of the specified token in the specified segment.
     ///
     /// # Arguments
@@ -723,6 +1256,31 @@ impl WhisperContext {
     }
 }
 
+impl fmt::Debug for WhisperContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WhisperContext")
+            .field("ctx", &self.ctx)
+            .field("spectrogram_initialized", &self.spectrogram_initialized)
+            .field("encode_complete", &self.encode_complete)
+            .field("decode_once", &self.decode_once)
+            .field("history", &self.history)
+            .field("logits_processors", &self.logits_processors.len())
+            .field("load_time", &self.load_time)
+            .finish()
+    }
+}
+
+/// Record the dimensions of a freshly loaded model on the current `whisper_init` span.
+#[cfg(feature = "tracing")]
+fn record_model_dims(ctx: *mut whisper_rs_sys::whisper_context) {
+    let span = tracing::Span::current();
+    unsafe {
+        span.record("n_vocab", whisper_rs_sys::whisper_n_vocab(ctx));
+        span.record("n_audio_ctx", whisper_rs_sys::whisper_n_audio_ctx(ctx));
+        span.record("n_text_ctx", whisper_rs_sys::whisper_n_text_ctx(ctx));
+    }
+}
+
 impl Drop for WhisperContext {
     #[inline]
     fn drop(&mut self) {
@@ -735,3 +1293,55 @@ impl Drop for WhisperContext {
 // concurrent usage is prevented by &mut self on methods that modify the struct
 unsafe impl Send for WhisperContext {}
 unsafe impl Sync for WhisperContext {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::mem::ManuallyDrop;
+
+    /// A context without a model, for testing the bookkeeping done before calling into whisper.cpp.
+    /// Never dropped, since there is nothing to free.
+    fn unloaded() -> ManuallyDrop<WhisperContext> {
+        ManuallyDrop::new(WhisperContext {
+            ctx: std::ptr::null_mut(),
+            spectrogram_initialized: true,
+            encode_complete: true,
+            decode_once: false,
+            n_decoded: 0,
+            history: Vec::new(),
+            logits_processors: Vec::new(),
+            load_time: Duration::ZERO,
+        })
+    }
+
+    #[test]
+    fn full_discards_logits() {
+        let mut ctx = unloaded();
+        // what a successful decode records
+        ctx.decode_once = true;
+        ctx.n_decoded = 3;
+        ctx.history = vec![1, 2, 3];
+        // what full, full_parallel, encode and lang_detect do once whisper.cpp returns
+        ctx.reset_decoder();
+        assert_eq!(ctx.logits().unwrap_err(), WhisperError::DecodeNotComplete);
+        let error = ctx.decode(&[4], 3, 1).unwrap_err();
+        assert!(matches!(
+            error.root_cause(),
+            WhisperError::NPastTooLarge { n_known: 0, .. }
+        ));
+    }
+
+    #[test]
+    fn decode_rejects_unknown_past_tokens() {
+        let mut ctx = unloaded();
+        ctx.history = vec![1, 2, 3];
+        let error = ctx.decode(&[4], 5, 1).unwrap_err();
+        assert_eq!(
+            error.root_cause(),
+            &WhisperError::NPastTooLarge {
+                n_past: 5,
+                n_known: 3
+            }
+        );
+    }
+}
@@ -1,5 +1,10 @@
+use crate::progress::Progress;
+use crate::segment::Segment;
 use std::ffi::{c_float, c_int, CString};
 use std::marker::PhantomData;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Instant;
 use whisper_rs_sys::whisper_token;
 
 pub enum SamplingStrategy {
@@ -19,8 +24,18 @@ impl Default for SamplingStrategy {
     }
 }
 
+/// A safe callback receiving every new segment while decoding.
+pub(crate) type SegmentCallback = Box<dyn FnMut(Segment) + Send>;
+
+/// A safe callback receiving progress updates while decoding.
+pub(crate) type ProgressCallback = Box<dyn FnMut(Progress) + Send>;
+
 pub struct FullParams<'a, 'b> {
     pub(crate) fp: whisper_rs_sys::whisper_full_params,
+    pub(crate) abort: Option<Arc<AtomicBool>>,
+    pub(crate) deadline: Option<Instant>,
+    pub(crate) on_segment: Option<SegmentCallback>,
+    pub(crate) on_progress: Option<ProgressCallback>,
     phantom_lang: PhantomData<&'a str>,
     phantom_tokens: PhantomData<&'b [c_int]>,
 }
@@ -54,6 +69,10 @@ impl<'a, 'b> FullParams<'a, 'b> {
 
         Self {
             fp,
+            abort: None,
+            deadline: None,
+            on_segment: None,
+            on_progress: None,
             phantom_lang: PhantomData,
             phantom_tokens: PhantomData,
         }
@@ -333,7 +352,7 @@ impl<'a, 'b> FullParams<'a, 'b> {
     /// # Safety
     /// Do not use this function unless you know what you are doing.
     /// * Be careful not to mutate the state of the whisper_context pointer returned in the callback.
-    ///  This could cause undefined behavior, as this violates the thread-safety guarantees of the underlying C library.
+    ///   This could cause undefined behavior, as this violates the thread-safety guarantees of the underlying C library.
     ///
     /// Defaults to None.
     pub unsafe fn set_start_encoder_callback(
@@ -355,6 +374,48 @@ impl<'a, 'b> FullParams<'a, 'b> {
     ) {
         self.fp.encoder_begin_callback_user_data = user_data;
     }
+
+    /// Set a flag to stop the transcription early.
+    ///
+    /// Once the flag is set, the transcription stops before the encoder runs on the next 30 second window,
+    /// and [WhisperContext::full](crate::WhisperContext::full) returns [WhisperError::Aborted](crate::WhisperError::Aborted).
+    ///
+    /// Defaults to None.
+    pub fn set_abort_flag(&mut self, abort: Arc<AtomicBool>) {
+        self.abort = Some(abort);
+    }
+
+    /// Set a function to receive every new segment as soon as it is decoded,
+    /// while [WhisperContext::full](crate::WhisperContext::full) is still running.
+    ///
+    /// Unlike [set_new_segment_callback](FullParams::set_new_segment_callback), this is safe,
+    /// and both can be used at the same time.
+    /// Segments of [WhisperContext::full_parallel](crate::WhisperContext::full_parallel) are only reported
+    /// for the first chunk.
+    ///
+    /// Defaults to None.
+    pub fn set_segment_callback<F>(&mut self, on_segment: F)
+    where
+        F: FnMut(Segment) + Send + 'static,
+    {
+        self.on_segment = Some(Box::new(on_segment));
+    }
+
+    /// Set a function to receive [Progress] updates while [WhisperContext::full](crate::WhisperContext::full) is running,
+    /// instead of having whisper.cpp print them with [set_print_progress](FullParams::set_print_progress).
+    ///
+    /// It is called before the encoder runs on every 30 second window, after every new segment,
+    /// and once more when the call succeeds.
+    /// Progress is measured by the end time of the latest segment, so it stands still during silence.
+    /// [WhisperContext::full_parallel](crate::WhisperContext::full_parallel) only reports progress of the first chunk.
+    ///
+    /// Defaults to None.
+    pub fn set_progress_callback<F>(&mut self, on_progress: F)
+    where
+        F: FnMut(Progress) + Send + 'static,
+    {
+        self.on_progress = Some(Box::new(on_progress));
+    }
 }
 
 // following implementations are safe
@@ -80,7 +80,7 @@ fn main() {
     let code = std::process::Command::new("cmake")
         .arg("--build")
         .arg(".")
-        .arg("--config Release")
+        .args(["--config", "Release"])
         .status()
         .expect("Failed to build libwhisper.a");
     if code.code() != Some(0) {
@@ -1,5 +1,6 @@
 #![allow(non_upper_case_globals)]
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
+#![allow(clippy::empty_docs)]
 
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));